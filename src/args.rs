@@ -1,7 +1,11 @@
 use argh::FromArgs;
+use libc::{EXIT_FAILURE, EXIT_SUCCESS};
 use log::LevelFilter;
-use std::{net::IpAddr, path::PathBuf};
+use std::{env, path::Path, path::PathBuf, process::exit};
 
+use crate::config::Address;
+use crate::ctl::{SeekTarget, VolumeChange};
+use crate::statusbar::StatusFormat;
 use crate::util::get_config_path;
 
 /// A client implementing the dbus MPRIS standard for mpd
@@ -14,15 +18,24 @@ pub struct Args {
     /// the port over which to connect to mpd
     #[argh(option, short = 'p')]
     pub port: Option<u16>,
-    /// the ip address over which to connect to mpd
+    /// the hostname, ip address or unix socket path over which to connect to mpd
     #[argh(option, short = 'a')]
-    pub addr: Option<IpAddr>,
-    /// number of times mpdris tries to reconnect to mpd before exiting. Set to -1 to retry infinite times
+    pub addr: Option<Address>,
+    /// number of times mpdris tries to reconnect to mpd after the config was reloaded
     #[argh(option, short = 'r')]
     pub retries: Option<isize>,
+    /// the mpd partition to control. May be given multiple times to expose each partition as its own player
+    #[argh(option)]
+    pub partition: Vec<String>,
     /// the path to the config file to use instead of the computed default
     #[argh(option, default = "get_config_path()")]
     pub config: PathBuf,
+    /// load and validate the config, then exit. Exits with a non-zero status if the config is invalid
+    #[argh(switch)]
+    pub check_config: bool,
+    /// print the config merged from the config file, environment and arguments, then exit
+    #[argh(switch)]
+    pub print_config: bool,
     /// the logging level to use. May be one of: trace, debug, info, warn, error
     #[argh(option, default = "log::LevelFilter::Info")]
     pub level: LevelFilter,
@@ -32,4 +45,233 @@ pub struct Args {
     /// set to act as a systemd service. Acts like a daemon without forking
     #[argh(switch)]
     pub service: bool,
+    /// write a line describing the status for status bars whenever it changes. May be one of: json, text
+    #[argh(option)]
+    pub status_format: Option<StatusFormat>,
+    /// the template of the status text, e.g. "{artist} - {title}"
+    #[argh(option, default = "String::from(\"{artist} - {title}\")")]
+    pub status_template: String,
+    /// the file or FIFO to write the status to instead of stdout
+    #[argh(option)]
+    pub status_output: Option<PathBuf>,
+    #[argh(subcommand)]
+    /// run a command instead of serving the MPRIS interface
+    pub command: Option<Subcommand>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Subcommand {
+    Ctl(CtlArgs),
+}
+
+#[derive(FromArgs)]
+/// Control a running mpdris, or any other MPRIS player, over D-Bus
+#[argh(subcommand, name = "ctl", help_triggers("-h", "--help"))]
+pub struct CtlArgs {
+    /// the player to control, named by its bus name without the leading org.mpris.MediaPlayer2., e.g. mpd.kitchen
+    #[argh(option, default = "String::from(\"mpd\")")]
+    pub player: String,
+    #[argh(subcommand)]
+    /// the command to send to the player
+    pub command: CtlCommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum CtlCommand {
+    Play(PlayCommand),
+    Pause(PauseCommand),
+    Toggle(ToggleCommand),
+    Stop(StopCommand),
+    Next(NextCommand),
+    Previous(PreviousCommand),
+    Seek(SeekCommand),
+    Volume(VolumeCommand),
+    Status(StatusCommand),
+}
+
+#[derive(FromArgs)]
+/// Start or resume playback
+#[argh(subcommand, name = "play", help_triggers("-h", "--help"))]
+pub struct PlayCommand {}
+
+#[derive(FromArgs)]
+/// Pause playback
+#[argh(subcommand, name = "pause", help_triggers("-h", "--help"))]
+pub struct PauseCommand {}
+
+#[derive(FromArgs)]
+/// Pause or resume playback
+#[argh(subcommand, name = "toggle", help_triggers("-h", "--help"))]
+pub struct ToggleCommand {}
+
+#[derive(FromArgs)]
+/// Stop playback
+#[argh(subcommand, name = "stop", help_triggers("-h", "--help"))]
+pub struct StopCommand {}
+
+#[derive(FromArgs)]
+/// Skip to the next song
+#[argh(subcommand, name = "next", help_triggers("-h", "--help"))]
+pub struct NextCommand {}
+
+#[derive(FromArgs)]
+/// Go back to the previous song
+#[argh(subcommand, name = "previous", help_triggers("-h", "--help"))]
+pub struct PreviousCommand {}
+
+#[derive(FromArgs)]
+/// Seek in the current song
+#[argh(subcommand, name = "seek", help_triggers("-h", "--help"))]
+pub struct SeekCommand {
+    #[argh(positional)]
+    /// the position to seek to in seconds or as minutes:seconds, e.g. 90 or 1:30.
+    /// Prefix with + or - to seek relative to the current position, e.g. +5s or -10
+    pub target: SeekTarget,
+}
+
+#[derive(FromArgs)]
+/// Set the volume
+#[argh(subcommand, name = "volume", help_triggers("-h", "--help"))]
+pub struct VolumeCommand {
+    #[argh(positional)]
+    /// the volume in percent, e.g. 50 or 50%.
+    /// Prefix with + or - to change the volume relative to the current volume, e.g. +5% or -10%
+    pub volume: VolumeChange,
+}
+
+#[derive(FromArgs)]
+/// Print the state of the player and its current song
+#[argh(subcommand, name = "status", help_triggers("-h", "--help"))]
+pub struct StatusCommand {
+    /// print the status as a JSON object
+    #[argh(switch)]
+    pub json: bool,
+    /// the template of the printed text, supporting the same placeholders as --status-template
+    #[argh(option, default = "String::from(\"[{state}] {artist} - {title}\")")]
+    pub template: String,
+}
+
+/// Parses the command line arguments like [argh::from_env], but allows negative values for `ctl seek` and
+/// `ctl volume` without ending the options using `--` first
+pub fn from_env() -> Args {
+    let mut args: Vec<String> = env::args().collect();
+    allow_negative_value(&mut args);
+
+    let cmd = Path::new(&args[0])
+        .file_name()
+        .map_or(args[0].as_str(), |n| n.to_str().unwrap_or("mpdris"));
+    let rest: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+
+    Args::from_args(&[cmd], &rest).unwrap_or_else(|early_exit| match early_exit.status {
+        Ok(()) => {
+            println!("{}", early_exit.output);
+            exit(EXIT_SUCCESS);
+        }
+        Err(()) => {
+            eprintln!("{}\nRun {cmd} --help for more information.", early_exit.output);
+            exit(EXIT_FAILURE);
+        }
+    })
+}
+
+/// Switches of mpdris, every other option is followed by a value
+const SWITCHES: [&str; 8] = [
+    "-v",
+    "--version",
+    "-h",
+    "--check-config",
+    "--print-config",
+    "--daemon",
+    "--service",
+    "--help",
+];
+/// Switches of `ctl`, every other option is followed by a value
+const CTL_SWITCHES: [&str; 2] = ["-h", "--help"];
+
+/// Inserts `--` before the value of `ctl seek` or `ctl volume` if it is negative, e.g. `-10%`,
+/// because argh would treat it as an option otherwise
+fn allow_negative_value(args: &mut Vec<String>) {
+    let Some(ctl) = subcommand_position(args, 1, &SWITCHES).filter(|&i| args[i] == "ctl") else {
+        return;
+    };
+    let Some(cmd) = subcommand_position(args, ctl + 1, &CTL_SWITCHES).filter(|&i| args[i] == "seek" || args[i] == "volume")
+    else {
+        return;
+    };
+
+    let is_negative = |a: &String| {
+        a.strip_prefix('-')
+            .is_some_and(|v| v.starts_with(|c: char| c.is_ascii_digit() || c == '.'))
+    };
+    if args.get(cmd + 1).is_some_and(is_negative) {
+        args.insert(cmd + 1, String::from("--"));
+    }
+}
+
+/// Returns the position of the subcommand in the arguments starting at start, skipping all options and their values.
+/// Returns None if there is no subcommand or the options are ended using `--`
+fn subcommand_position(args: &[String], start: usize, switches: &[&str]) -> Option<usize> {
+    let mut i = start;
+    while let Some(arg) = args.get(i) {
+        if arg == "--" {
+            return None;
+        } else if !arg.starts_with('-') {
+            return Some(i);
+        }
+
+        i += if switches.contains(&arg.as_str()) { 1 } else { 2 };
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_allow_negative_value() {
+        let mut result = args(&["mpdris", "ctl", "--player", "mpd.kitchen", "volume", "-10%"]);
+        allow_negative_value(&mut result);
+
+        assert_eq!(
+            result,
+            args(&["mpdris", "ctl", "--player", "mpd.kitchen", "volume", "--", "-10%"])
+        );
+    }
+
+    #[test]
+    fn test_option_values_skipped() {
+        let mut result = args(&[
+            "mpdris", "--level", "debug", "--daemon", "ctl", "--player", "volume", "seek", "-10",
+        ]);
+        allow_negative_value(&mut result);
+
+        assert_eq!(
+            result,
+            args(&[
+                "mpdris", "--level", "debug", "--daemon", "ctl", "--player", "volume", "seek", "--", "-10"
+            ])
+        );
+
+        let expected = args(&["mpdris", "ctl", "status", "--template", "volume", "-1"]);
+        let mut result = expected.clone();
+        allow_negative_value(&mut result);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_options_kept() {
+        let expected = args(&["mpdris", "ctl", "seek", "--help"]);
+        let mut result = expected.clone();
+        allow_negative_value(&mut result);
+
+        assert_eq!(result, expected);
+    }
 }
@@ -0,0 +1,306 @@
+#[cfg(test)]
+mod tests;
+
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use super::status::{ReplayGainMode, TriState};
+use super::{Error, ErrorKind, Result};
+
+/// A single command sent to MPD.
+///
+/// Use the typed constructors like [Self::play] for known commands or [Self::new] together with
+/// [Self::arg] for everything else. Arguments are quoted as described in the
+/// [MPD protocol](https://mpd.readthedocs.io/en/stable/protocol.html#escaping-string-values)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command(String);
+
+/// A list of commands which MPD executes at once, created using [CommandList::new] and [CommandList::push].
+///
+/// The list is sent using `command_list_ok_begin`, so the response to every command is returned separately
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandList(Vec<Command>);
+
+/// Something that can be sent to MPD, either a [Command] or a [CommandList]
+pub trait Request: Display {
+    /// The response of MPD to this request
+    type Response;
+
+    /// Creates the response from the responses MPD sent, separated by `list_OK`
+    fn response(responses: Vec<Vec<(String, String)>>) -> Self::Response;
+
+    /// Checks that the request can be sent to MPD.
+    /// Line breaks cannot be escaped, so an argument containing one would be sent as multiple commands
+    ///
+    /// ## Errors
+    /// - WrongArgument if an argument contains a line break
+    fn validate(&self) -> Result<()>;
+}
+
+impl Command {
+    /// Creates a command without any arguments
+    pub fn new(name: &str) -> Self {
+        Self(name.to_string())
+    }
+
+    /// Appends an argument to the command, quoting it if necessary
+    pub fn arg(mut self, arg: impl Display) -> Self {
+        let arg = arg.to_string();
+        self.0.push(' ');
+
+        if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '\\')) {
+            self.0.push('"');
+            for chr in arg.chars() {
+                if chr == '"' || chr == '\\' {
+                    self.0.push('\\');
+                }
+                self.0.push(chr);
+            }
+            self.0.push('"');
+        } else {
+            self.0.push_str(&arg);
+        }
+
+        self
+    }
+
+    pub fn ping() -> Self {
+        Self::new("ping")
+    }
+
+    pub fn password(password: &str) -> Self {
+        Self::new("password").arg(password)
+    }
+
+    pub fn binarylimit(size: usize) -> Self {
+        Self::new("binarylimit").arg(size)
+    }
+
+    /// Switches the client to the partition with the given name
+    pub fn partition(name: &str) -> Self {
+        Self::new("partition").arg(name)
+    }
+
+    /// Waits until one of the given subsystems changes
+    pub fn idle(subsystems: &[&str]) -> Self {
+        subsystems.iter().fold(Self::new("idle"), |cmd, s| cmd.arg(s))
+    }
+
+    pub fn status() -> Self {
+        Self::new("status")
+    }
+
+    pub fn currentsong() -> Self {
+        Self::new("currentsong")
+    }
+
+    pub fn play() -> Self {
+        Self::new("play")
+    }
+
+    pub fn playid(id: u32) -> Self {
+        Self::new("playid").arg(id)
+    }
+
+    /// Pauses or resumes playback, toggles it if state is None
+    pub fn pause(state: Option<bool>) -> Self {
+        match state {
+            Some(state) => Self::new("pause").arg(u8::from(state)),
+            None => Self::new("pause"),
+        }
+    }
+
+    pub fn stop() -> Self {
+        Self::new("stop")
+    }
+
+    pub fn previous() -> Self {
+        Self::new("previous")
+    }
+
+    /// Seeks to time in the song with the given id
+    pub fn seekid(id: u32, time: Duration) -> Self {
+        Self::new("seekid").arg(id).arg(seconds(time))
+    }
+
+    /// Seeks to time in the current song
+    pub fn seekcur(time: Duration) -> Self {
+        Self::new("seekcur").arg(seconds(time))
+    }
+
+    /// Seeks relative to the current position in the current song
+    pub fn seekcur_relative(is_positive: bool, offset: Duration) -> Self {
+        let prefix = if is_positive { '+' } else { '-' };
+        Self::new("seekcur").arg(format_args!("{prefix}{}", seconds(offset)))
+    }
+
+    pub fn setvol(volume: u8) -> Self {
+        Self::new("setvol").arg(volume)
+    }
+
+    pub fn repeat(state: bool) -> Self {
+        Self::new("repeat").arg(u8::from(state))
+    }
+
+    pub fn single(state: TriState) -> Self {
+        Self::new("single").arg(state.to_mpd())
+    }
+
+    pub fn consume(state: TriState) -> Self {
+        Self::new("consume").arg(state.to_mpd())
+    }
+
+    /// Sets the crossfade between songs in seconds, 0 disables it
+    pub fn crossfade(secs: u32) -> Self {
+        Self::new("crossfade").arg(secs)
+    }
+
+    /// Sets the volume threshold in decibels used by MixRamp
+    pub fn mixrampdb(db: f64) -> Self {
+        Self::new("mixrampdb").arg(db)
+    }
+
+    pub fn replay_gain_mode(mode: ReplayGainMode) -> Self {
+        Self::new("replay_gain_mode").arg(mode.as_str())
+    }
+
+    pub fn replay_gain_status() -> Self {
+        Self::new("replay_gain_status")
+    }
+
+    pub fn random(state: bool) -> Self {
+        Self::new("random").arg(u8::from(state))
+    }
+
+    pub fn playlistinfo() -> Self {
+        Self::new("playlistinfo")
+    }
+
+    pub fn playlistid(id: u32) -> Self {
+        Self::new("playlistid").arg(id)
+    }
+
+    /// Adds uri to the queue at pos, or at the end if pos is None
+    pub fn addid(uri: &str, pos: Option<u32>) -> Self {
+        let cmd = Self::new("addid").arg(uri);
+        match pos {
+            Some(pos) => cmd.arg(pos),
+            None => cmd,
+        }
+    }
+
+    pub fn deleteid(id: u32) -> Self {
+        Self::new("deleteid").arg(id)
+    }
+
+    pub fn clear() -> Self {
+        Self::new("clear")
+    }
+
+    pub fn listplaylists() -> Self {
+        Self::new("listplaylists")
+    }
+
+    pub fn load(name: &str) -> Self {
+        Self::new("load").arg(name)
+    }
+
+    /// Reads the sticker with the given name of the song with the given uri
+    pub fn sticker_get(uri: &str, name: &str) -> Self {
+        Self::new("sticker").arg("get").arg("song").arg(uri).arg(name)
+    }
+
+    /// Sets the sticker with the given name of the song with the given uri
+    pub fn sticker_set(uri: &str, name: &str, value: impl Display) -> Self {
+        Self::new("sticker").arg("set").arg("song").arg(uri).arg(name).arg(value)
+    }
+
+    pub fn outputs() -> Self {
+        Self::new("outputs")
+    }
+
+    pub fn enableoutput(id: u32) -> Self {
+        Self::new("enableoutput").arg(id)
+    }
+
+    pub fn disableoutput(id: u32) -> Self {
+        Self::new("disableoutput").arg(id)
+    }
+
+    pub fn toggleoutput(id: u32) -> Self {
+        Self::new("toggleoutput").arg(id)
+    }
+
+    /// Reads the picture embedded in the song with the given uri, starting at offset
+    pub fn readpicture(uri: &str, offset: usize) -> Self {
+        Self::new("readpicture").arg(uri).arg(offset)
+    }
+
+    /// Reads the cover file in the directory of the song with the given uri, starting at offset
+    pub fn albumart(uri: &str, offset: usize) -> Self {
+        Self::new("albumart").arg(uri).arg(offset)
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Request for Command {
+    type Response = Vec<(String, String)>;
+
+    fn response(responses: Vec<Vec<(String, String)>>) -> Self::Response {
+        responses.into_iter().next().unwrap_or_default()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.0.contains(['\n', '\r']) {
+            return Err(Error::new(ErrorKind::WrongArgument, "Arguments must not contain line breaks"));
+        }
+        Ok(())
+    }
+}
+
+impl CommandList {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Appends a command to the list
+    pub fn push(mut self, cmd: Command) -> Self {
+        self.0.push(cmd);
+        self
+    }
+}
+
+impl Display for CommandList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("command_list_ok_begin\n")?;
+        for cmd in &self.0 {
+            writeln!(f, "{cmd}")?;
+        }
+        f.write_str("command_list_end")
+    }
+}
+
+impl Request for CommandList {
+    /// The responses of the commands in the order they were pushed
+    type Response = Vec<Vec<(String, String)>>;
+
+    fn response(mut responses: Vec<Vec<(String, String)>>) -> Self::Response {
+        // the response after the last list_OK is always empty
+        responses.pop();
+        responses
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.0.iter().try_for_each(Command::validate)
+    }
+}
+
+/// Formats a duration as fractional seconds
+fn seconds(time: Duration) -> String {
+    format!("{}.{:03}", time.as_secs(), time.subsec_millis())
+}
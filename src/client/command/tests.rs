@@ -0,0 +1,96 @@
+use super::*;
+
+#[test]
+fn test_command_without_args() {
+    assert_eq!(Command::status().to_string(), "status");
+}
+
+#[test]
+fn test_plain_args() {
+    assert_eq!(
+        Command::addid("Celeste/Resurrections.mp3", Some(3)).to_string(),
+        "addid Celeste/Resurrections.mp3 3"
+    );
+    assert_eq!(Command::repeat(true).to_string(), "repeat 1");
+}
+
+#[test]
+fn test_option_args() {
+    assert_eq!(Command::single(TriState::OneShot).to_string(), "single oneshot");
+    assert_eq!(Command::consume(TriState::Off).to_string(), "consume 0");
+    assert_eq!(Command::mixrampdb(-17.5).to_string(), "mixrampdb -17.5");
+    assert_eq!(
+        Command::replay_gain_mode(ReplayGainMode::Album).to_string(),
+        "replay_gain_mode album"
+    );
+}
+
+#[test]
+fn test_quoted_args() {
+    let result = Command::load("my \"best\" songs").to_string();
+    let expected = r#"load "my \"best\" songs""#;
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_escaped_backslash() {
+    let result = Command::addid(r"AC\DC/Back in Black.mp3", None).to_string();
+    let expected = r#"addid "AC\\DC/Back in Black.mp3""#;
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_single_quote_and_empty_arg() {
+    assert_eq!(Command::load("don't").to_string(), r#"load "don't""#);
+    assert_eq!(Command::password("").to_string(), r#"password """#);
+}
+
+#[test]
+fn test_seek_time() {
+    assert_eq!(Command::seekid(1, Duration::ZERO).to_string(), "seekid 1 0.000");
+    assert_eq!(Command::seekcur(Duration::from_millis(1050)).to_string(), "seekcur 1.050");
+    assert_eq!(
+        Command::seekcur_relative(false, Duration::from_secs(10)).to_string(),
+        "seekcur -10.000"
+    );
+}
+
+#[test]
+fn test_idle() {
+    let result = Command::idle(&["player", "mixer"]).to_string();
+
+    assert_eq!(result, "idle player mixer");
+}
+
+#[test]
+fn test_command_list() {
+    let result = CommandList::new()
+        .push(Command::clear())
+        .push(Command::load("fav"))
+        .push(Command::play())
+        .to_string();
+    let expected = "command_list_ok_begin\nclear\nload fav\nplay\ncommand_list_end";
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_command_list_response() {
+    let responses = vec![vec![(String::from("Id"), String::from("5"))], Vec::new(), Vec::new()];
+    let expected = vec![vec![(String::from("Id"), String::from("5"))], Vec::new()];
+
+    assert_eq!(CommandList::response(responses), expected);
+}
+
+#[test]
+fn test_line_breaks_rejected() {
+    let injected = Command::load("songs\nclear");
+    assert_eq!(injected.validate().unwrap_err().kind, ErrorKind::WrongArgument);
+    assert!(Command::addid("a.mp3\r", None).validate().is_err());
+
+    let list = CommandList::new().push(Command::play()).push(injected);
+    assert!(list.validate().is_err());
+    assert!(Command::load("my \"best\" songs").validate().is_ok());
+}
@@ -1,51 +1,189 @@
-use std::net::{IpAddr, SocketAddr};
+use std::hash::{BuildHasher, RandomState};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr as UnixSocketAddr, UnixStream as StdUnixStream};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
 use async_std::io::{self, BufReader, BufWriter};
-use async_std::net::TcpStream;
-use async_std::task::{block_on, sleep};
+use async_std::net::{TcpStream, ToSocketAddrs};
+use async_std::os::unix::net::UnixStream;
+use async_std::task::{sleep, spawn_blocking};
 
-use const_format::concatcp;
-use futures_util::io::{ReadHalf, WriteHalf};
+use futures_util::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
 use futures_util::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
-use libc::SIGTERM;
-use log::{debug, error, info, warn};
+use log::{debug, info, warn};
 
+use super::command::{Command, Request};
 use super::error::MPDResult as Result;
 use super::error::{Error, ErrorKind};
-use crate::config::{Config, config};
-use crate::util::send_sig;
+use super::response::{self, Line};
+use crate::config::{Address, Server, config};
 
-/// How many bytes MPD sends at once
-const SIZE_LIMIT: usize = 1024;
+/// How many bytes of binary data like covers MPD sends at once.
+/// Each chunk takes a round trip, so this is much larger than MPD's default of 8 KiB
+const SIZE_LIMIT: usize = 64 * 1024;
+/// Delay before the first retry to connect to MPD
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Maximum delay between two retries to connect to MPD
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
 
 pub struct MPDConnection {
-    reader: BufReader<ReadHalf<TcpStream>>,
-    writer: BufWriter<WriteHalf<TcpStream>>,
+    reader: BufReader<ReadHalf<Stream>>,
+    writer: BufWriter<WriteHalf<Stream>>,
+    /// The label of the player this connection belongs to, used to look up the server to connect to.
+    /// See [Config::server]
+    label: Option<String>,
+}
+
+/// A stream to MPD, either over TCP or over a unix domain socket
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+    /// Placeholder until a connection is established, all reads & writes fail
+    Disconnected,
+}
+
+impl Stream {
+    async fn connect(addr: &Address, port: u16) -> io::Result<Self> {
+        match addr {
+            Address::Host(host) => {
+                let mut last_err = None;
+
+                // try every address the host resolves to, e.g. both IPv6 and IPv4
+                for addr in (host.as_str(), port).to_socket_addrs().await? {
+                    debug!("Trying to connect to {addr}");
+                    match TcpStream::connect(addr).await {
+                        Ok(stream) => return Ok(Self::Tcp(stream)),
+                        Err(err) => last_err = Some(err),
+                    }
+                }
+
+                Err(last_err.unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("`{host}` did not resolve to any address"))
+                }))
+            }
+            Address::Socket(path) => Ok(Self::Unix(UnixStream::connect(path).await?)),
+            Address::Abstract(name) => {
+                let addr = UnixSocketAddr::from_abstract_name(name.as_bytes())?;
+                // async-std cannot connect to abstract sockets, so we connect using std instead
+                let stream = spawn_blocking(move || StdUnixStream::connect_addr(&addr)).await?;
+
+                Ok(Self::Unix(stream.into()))
+            }
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_read(cx, buf),
+            Self::Unix(s) => Pin::new(s).poll_read(cx, buf),
+            Self::Disconnected => Poll::Ready(Err(io::ErrorKind::NotConnected.into())),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_write(cx, buf),
+            Self::Unix(s) => Pin::new(s).poll_write(cx, buf),
+            Self::Disconnected => Poll::Ready(Err(io::ErrorKind::NotConnected.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_flush(cx),
+            Self::Unix(s) => Pin::new(s).poll_flush(cx),
+            Self::Disconnected => Poll::Ready(Err(io::ErrorKind::NotConnected.into())),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_close(cx),
+            Self::Unix(s) => Pin::new(s).poll_close(cx),
+            Self::Disconnected => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// Returns a human-readable description of where the given server points to
+pub fn describe(server: &Server) -> String {
+    let addr = &server.addr;
+    let description = match addr {
+        Address::Host(host) => format!("host: {host} using port: {}", server.port),
+        Address::Socket(_) | Address::Abstract(_) => format!("socket: {addr}"),
+    };
+
+    match &server.partition {
+        Some(partition) => format!("{description} in partition: {partition}"),
+        None => description,
+    }
 }
 
 impl MPDConnection {
-    pub async fn new(c: &Config) -> Result<Self> {
-        let (r, w) = Self::connect(c.addr, c.port, c.retries).await?;
+    /// Creates a connection that is not connected yet, use [Self::reconnect] to connect it.
+    /// The connection connects to the server of the player with the given label
+    pub fn disconnected(label: Option<String>) -> Self {
+        let (r, w) = Stream::Disconnected.split();
 
-        let mut conn = Self { reader: r, writer: w };
+        Self {
+            reader: BufReader::new(r),
+            writer: BufWriter::new(w),
+            label,
+        }
+    }
 
-        conn.after_connect().await?;
-        Ok(conn)
+    /// Returns the label of the player this connection belongs to, or None if it connects to the server set in the config
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
     }
 
-    pub async fn request_data(&mut self, request: &str) -> Result<Vec<(String, String)>> {
-        match self.request_data_in(request).await {
+    /// Returns the server this connection connects to, as currently configured
+    pub async fn server(&self) -> Server {
+        config().read().await.server(self.label.as_deref())
+    }
+
+    /// Sends a [Command] or [CommandList](super::CommandList) to MPD and returns its response
+    pub async fn request_data<R: Request>(&mut self, request: &R) -> Result<R::Response> {
+        request.validate()?;
+        Ok(R::response(self.request(&request.to_string()).await?.0))
+    }
+
+    /// Sends a command to MPD whose response may contain binary data.
+    /// Returns the key-value pairs of the response together with the concatenated binary data
+    pub async fn request_binary(&mut self, cmd: &Command) -> Result<(Vec<(String, String)>, Vec<u8>)> {
+        cmd.validate()?;
+        let (responses, binary) = self.request(&cmd.to_string()).await?;
+
+        Ok((Command::response(responses), binary))
+    }
+
+    async fn request(&mut self, request: &str) -> Result<(Vec<Vec<(String, String)>>, Vec<u8>)> {
+        match self.request_in(request).await {
             Ok(ok) => Ok(ok),
+            // MPD answered the request with an error, so the connection itself is fine
+            Err(err) if err.kind.is_ack() => Err(err),
+            // only try to reconnect once, so that requests fail fast while MPD is unreachable.
+            // Waiting for MPD to come back is up to the idle task
             Err(err) => {
                 warn!("Failed to read from MPD connection, reconnecting: {err}");
-                self.reconnect().await?;
-                self.request_data_in(request).await
+                self.reconnect_with(0).await?;
+                self.request_in(request).await
             }
         }
     }
 
-    async fn request_data_in(&mut self, request: &str) -> Result<Vec<(String, String)>> {
+    async fn request_data_in(&mut self, cmd: &Command) -> Result<Vec<(String, String)>> {
+        cmd.validate()?;
+        Ok(Command::response(self.request_in(&cmd.to_string()).await?.0))
+    }
+
+    async fn request_in(&mut self, request: &str) -> Result<(Vec<Vec<(String, String)>>, Vec<u8>)> {
         let request = format!("{request}\n");
 
         self.writer.write_all(request.as_bytes()).await?;
@@ -54,13 +192,14 @@ impl MPDConnection {
         self.read_data().await
     }
 
-    async fn read_data(&mut self) -> Result<Vec<(String, String)>> {
-        let mut data: Vec<(String, String)> = Vec::new();
-        let mut buf = String::new();
-        let mut failed_parses: u8 = 0;
+    /// Reads a response, split into the responses of the single commands if it is the response to a command list
+    async fn read_data(&mut self) -> Result<(Vec<Vec<(String, String)>>, Vec<u8>)> {
+        let mut data: Vec<Vec<(String, String)>> = vec![Vec::new()];
+        let mut binary: Vec<u8> = Vec::new();
+        let mut buf: Vec<u8> = Vec::new();
 
         loop {
-            let bytes_read = self.reader.read_line(&mut buf).await?;
+            let bytes_read = self.reader.read_until(b'\n', &mut buf).await?;
 
             if bytes_read == 0 {
                 return Err(io::Error::new(
@@ -68,52 +207,65 @@ impl MPDConnection {
                     "Connection reached EOF without indicator for end of response",
                 )
                 .into());
-            } else if buf.starts_with("OK") {
-                // lines starting with OK indicate the end of response
-                break;
-            } else if buf.starts_with("ACK") {
-                return Err(Error::try_from_mpd(buf)?);
             }
 
-            let mut parts = buf.split(": ");
-
-            if let (Some(k), Some(v)) = (parts.next(), parts.next()) {
-                data.push((k.to_string(), v.trim().to_string()));
-            } else {
-                failed_parses += 1;
-                warn!("Could not split response line into key-value pair (failed parses {failed_parses})");
-                if failed_parses >= 3 {
-                    return Err(Error::new_string(
-                        ErrorKind::KeyValueError,
-                        format!("Failed to parse {failed_parses} lines into key-value pairs"),
-                    ));
+            match response::parse_line(&buf) {
+                Line::Ok => break,
+                Line::ListOk => data.push(Vec::new()),
+                Line::Ack(ack) => return Err(Error::try_from_mpd(ack)?),
+                // binary: <len> is followed by len bytes of binary data and a newline
+                Line::Pair(k, v) if k == "binary" => {
+                    let len: usize = v.parse().map_err(|_e| {
+                        Error::new_string(ErrorKind::KeyValueError, format!("Invalid length of binary data: {v}"))
+                    })?;
+
+                    let start = binary.len();
+                    binary.resize(start + len, 0);
+                    self.reader.read_exact(&mut binary[start..]).await?;
+                    self.reader.read_exact(&mut [0u8]).await?;
+
+                    data.last_mut().unwrap().push((k, v));
+                }
+                Line::Pair(k, v) => data.last_mut().unwrap().push((k, v)),
+                Line::Invalid => {
+                    warn!(
+                        "Ignoring response line that is not a key-value pair: {}",
+                        String::from_utf8_lossy(&buf).trim_end()
+                    );
                 }
             }
 
             buf.clear();
         }
 
-        Ok(data)
+        Ok((data, binary))
     }
 
-    async fn after_connect(&mut self) -> Result<()> {
+    async fn after_connect(&mut self, server: &Server) -> Result<()> {
         self.read_data().await?;
+        if let Some(password) = &server.password {
+            debug!("Sending password");
+            self.request_data_in(&Command::password(password)).await?;
+        }
         debug!("Setting binary output limit to {SIZE_LIMIT} bytes");
-        self.request_data_in(concatcp!("binarylimit ", SIZE_LIMIT)).await?;
+        self.request_data_in(&Command::binarylimit(SIZE_LIMIT)).await?;
+        if let Some(partition) = &server.partition {
+            debug!("Switching to partition {partition}");
+            self.request_data_in(&Command::partition(partition)).await?;
+        }
 
         Ok(())
     }
 
     async fn connect(
-        addr: IpAddr,
+        addr: &Address,
         port: u16,
         retries: isize,
-    ) -> io::Result<(BufReader<ReadHalf<TcpStream>>, BufWriter<WriteHalf<TcpStream>>)> {
-        let mut attempts = 0;
-        let addr = &SocketAddr::new(addr, port);
+    ) -> io::Result<(BufReader<ReadHalf<Stream>>, BufWriter<WriteHalf<Stream>>)> {
+        let mut attempt: u32 = 0;
 
         loop {
-            match TcpStream::connect(addr).await {
+            match Stream::connect(addr, port).await {
                 Ok(stream) => {
                     let (r, w) = stream.split();
 
@@ -121,39 +273,82 @@ impl MPDConnection {
                     return Ok((BufReader::new(r), BufWriter::new(w)));
                 }
                 Err(err) => {
-                    if retries > 0 {
-                        warn!("Could not connect (tries left {}): {err}", retries - attempts);
-
-                        attempts += 1;
-                        if attempts > retries {
-                            return Err(err);
-                        }
+                    // a negative amount of retries means waiting for MPD indefinitely
+                    if retries < 0 {
+                        warn!("Could not connect, waiting for MPD: {err}");
+                    } else if (attempt as isize) < retries {
+                        warn!("Could not connect (tries left {}): {err}", retries - attempt as isize);
                     } else {
-                        error!("Could not connect: {err}");
+                        return Err(err);
                     }
 
-                    debug!("Retrying in 3 seconds");
-                    sleep(Duration::from_secs(3)).await;
+                    let delay = retry_delay(attempt, jitter());
+                    debug!("Retrying in {delay:.1?}");
+                    sleep(delay).await;
+                    attempt = attempt.saturating_add(1);
                 }
             }
         }
     }
 
+    /// Reconnects to MPD, retrying as often as configured.
+    /// Never waits for MPD indefinitely, as the connection stays locked meanwhile
     pub async fn reconnect(&mut self) -> Result<()> {
-        {
-            let c = config().read().await;
-
-            info!("Reconnecting to server on ip-address: {} using port: {}", c.addr, c.port);
-            let (r, w) = Self::connect(c.addr, c.port, c.retries).await.unwrap_or_else(|e| {
-                error!("Failed to reconnect to MPD, exiting: {e}");
-                send_sig(std::process::id(), SIGTERM).expect("should always be able to send signal");
-                block_on(std::future::pending())
-            });
-
-            self.reader = r;
-            self.writer = w;
-        }
+        let retries = config().read().await.retries.max(0);
+        info!("Reconnecting to server on {}", describe(&self.server().await));
+
+        self.reconnect_with(retries).await
+    }
 
-        self.after_connect().await
+    /// Reconnects to MPD, retrying the given amount of times or indefinitely if retries is negative
+    pub async fn reconnect_with(&mut self, retries: isize) -> Result<()> {
+        // the config must not stay locked while waiting for MPD, so it can be reloaded in the meantime
+        let server = self.server().await;
+
+        let (r, w) = Self::connect(&server.addr, server.port, retries).await?;
+
+        self.reader = r;
+        self.writer = w;
+
+        self.after_connect(&server).await
+    }
+}
+
+/// Returns the delay before the retry following the given failed attempt to connect.
+/// The delay doubles with every attempt up to [MAX_RETRY_DELAY],
+/// jitter between 0 and 1 then scales it to between half and all of that
+pub fn retry_delay(attempt: u32, jitter: f64) -> Duration {
+    let delay = INITIAL_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_DELAY);
+
+    delay.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+/// Returns a random number between 0 and 1, so that multiple clients don't reconnect at the same time
+pub fn jitter() -> f64 {
+    let random = RandomState::new().hash_one(std::process::id());
+
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(0, 1.0), INITIAL_RETRY_DELAY);
+        assert_eq!(retry_delay(3, 1.0), INITIAL_RETRY_DELAY * 8);
+        assert_eq!(retry_delay(3, 0.0), INITIAL_RETRY_DELAY * 4);
+        assert_eq!(retry_delay(100, 1.0), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX, 0.0), MAX_RETRY_DELAY / 2);
+    }
+
+    #[test]
+    fn test_jitter() {
+        for _ in 0..100 {
+            assert!((0.0..=1.0).contains(&jitter()));
+        }
     }
 }
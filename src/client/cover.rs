@@ -0,0 +1,277 @@
+use async_std::{fs, stream::StreamExt, sync::Mutex};
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+
+use self::cache::CoverCache;
+use super::connection::MPDConnection;
+use super::{Command, Result, Song};
+use crate::config::config;
+use crate::util::get_cache_dir;
+
+mod cache;
+pub mod pattern;
+
+const IMG_EXTS: [&str; 10] = ["jpg", "jpeg", "png", "webp", "avif", "jxl", "bmp", "gif", "heif", "heic"];
+
+/// Constructor of a command reading a picture in chunks, either readpicture or albumart
+type PictureCommand = fn(&str, usize) -> Command;
+
+/// The cover cache, loaded on first use
+static CACHE: Mutex<Option<CoverCache>> = Mutex::new(None);
+
+/// Searches the cover of the given song.
+///
+/// The cover cache is checked first, then the cover & music directory get searched and lastly MPD
+/// is asked for the picture embedded in the song. The result of the search is recorded in the cache.
+/// The cache is not locked while searching, so that players don't have to wait for each other
+pub async fn find_cover(song: &Song, conn: &mut MPDConnection) -> Option<PathBuf> {
+    let song_key = format!("song:{}", song.uri.display());
+    let album_key = album_key(song);
+
+    let dir = {
+        let mut guard = CACHE.lock().await;
+        let cache = load_cache(&mut guard).await;
+
+        for key in [&song_key, &album_key] {
+            if let Some(cover) = cache.get(key).await {
+                debug!("found cover of '{}' in cache under '{key}'", song.uri.display());
+                // the entry was used now, which has to survive a restart to evict the right entries
+                if let Err(err) = cache.save().await {
+                    warn!("Could not save the cover cache: {err}");
+                }
+                return cover;
+            }
+        }
+
+        cache.dir().to_path_buf()
+    };
+
+    let (max_size, local_song) = {
+        let c = config().read().await;
+        (c.cover_cache_size * 1024 * 1024, c.music_directory.join(&song.uri))
+    };
+
+    // the key, cover, source & size of the cache entry
+    let (key, cover, source, size) = if let Some((path, song_specific)) = find_local_cover(song).await {
+        // covers belonging to a single song must not be used for the whole album
+        let key = if song_specific { song_key } else { album_key };
+        (key, Some(path.clone()), Some(path), 0)
+    } else {
+        let source = is_file(&local_song).await.then_some(local_song.clone());
+
+        match fetch_mpd_cover(conn, &song.uri, &dir, &album_key).await {
+            Ok(Some((path, size))) => (album_key, Some(path), source, size),
+            Ok(None) => {
+                debug!("no cover found, recording miss");
+                // adding a cover to the directory of the song changes its modification time
+                let source = local_song.parent().filter(|_| source.is_some()).map(Path::to_path_buf);
+                (song_key, None, source, 0)
+            }
+            Err(err) => {
+                warn!("Could not get cover from MPD: {err}");
+                return None;
+            }
+        }
+    };
+
+    let mut guard = CACHE.lock().await;
+    let cache = load_cache(&mut guard).await;
+    if cache.insert(key, cover.clone(), source, size, max_size).await
+        && let Err(err) = cache.save().await
+    {
+        warn!("Could not save the cover cache: {err}");
+    }
+
+    cover
+}
+
+/// Returns the cover cache, loading it if it is not loaded yet
+async fn load_cache(cache: &mut Option<CoverCache>) -> &mut CoverCache {
+    if cache.is_none() {
+        *cache = Some(CoverCache::load(get_cache_dir().join("covers")).await);
+    }
+
+    cache.as_mut().unwrap()
+}
+
+/// Returns the key under which covers belonging to the album of the song are cached.
+/// The key is made from the album artist & album or the directory of the song if it has no album
+fn album_key(song: &Song) -> String {
+    match &song.album {
+        Some(album) => {
+            let artist = song.album_artists.first().or(song.artists.first()).map_or("", |a| a);
+            format!("album:{artist}/{album}")
+        }
+        None => format!("dir:{}", song.uri.parent().unwrap_or(Path::new("")).display()),
+    }
+}
+
+async fn is_file(path: &Path) -> bool {
+    fs::metadata(path).await.is_ok_and(|m| m.is_file())
+}
+
+/// Searches for an image file belonging to the song using the configured cover patterns.
+///
+/// Returns the path of the cover and whether the cover belongs to only this song
+async fn find_local_cover(song: &Song) -> Option<(PathBuf, bool)> {
+    // the paths to search paired with whether a cover found there belongs to only this song
+    let paths: Vec<_> = {
+        let c = config().read().await;
+
+        c.cover_patterns
+            .iter()
+            .filter_map(|p| {
+                let path = pattern::render(p, song, &c.music_directory, &c.cover_directory)?;
+                Some((path, pattern::is_song_specific(p)))
+            })
+            .collect()
+    };
+
+    for (path, song_specific) in paths {
+        debug!("searching path '{}' for cover", path.display());
+
+        if let Some(cover) = find_image(&path).await {
+            debug!("found cover '{}'", cover.display());
+            return Some((cover, song_specific));
+        }
+    }
+
+    None
+}
+
+/// Searches the parent directory of path for an image whose name matches the file name of path,
+/// either as is or with one of [IMG_EXTS] added, ignoring case.
+/// Extensions earlier in [IMG_EXTS] are preferred
+async fn find_image(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    let mut entries = fs::read_dir(path.parent()?).await.ok()?;
+
+    let mut best: Option<(usize, PathBuf)> = None;
+    while let Some(Ok(entry)) = entries.next().await {
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str().map(str::to_lowercase) else {
+            continue;
+        };
+
+        let rank = match file_name.rsplit_once('.') {
+            Some((stem, ext)) if stem == name => IMG_EXTS.iter().position(|e| *e == ext),
+            // the pattern may already contain the extension
+            Some((_, ext)) if file_name == name => IMG_EXTS.iter().position(|e| *e == ext),
+            _ => None,
+        };
+
+        let path: PathBuf = entry.path().into();
+        if let Some(rank) = rank
+            && best.as_ref().is_none_or(|(r, _)| rank < *r)
+            && is_file(&path).await
+        {
+            best = Some((rank, path));
+        }
+    }
+
+    best.map(|(_, path)| path)
+}
+
+/// Asks MPD for the picture embedded in the song with the given uri, falling back to the cover
+/// file in the directory of the song. The picture is then written to the given directory,
+/// using the hash of name as its file name.
+///
+/// Returns the path & size of the written picture or None if MPD has no picture for the song.
+/// Fails only if the connection to MPD fails, errors sent by MPD are treated like a missing picture
+async fn fetch_mpd_cover(conn: &mut MPDConnection, uri: &Path, dir: &Path, name: &str) -> Result<Option<(PathBuf, u64)>> {
+    let uri_str = uri.to_string_lossy();
+
+    let mut picture = None;
+    for (name, cmd) in [
+        ("readpicture", Command::readpicture as PictureCommand),
+        ("albumart", Command::albumart),
+    ] {
+        match read_picture(conn, cmd, &uri_str).await {
+            Ok(Some(p)) => {
+                debug!("got cover for '{uri_str}' using {name}");
+                picture = Some(p);
+                break;
+            }
+            Ok(None) => {}
+            // e.g. MPD before 0.22 does not know readpicture, but albumart may still work
+            Err(err) if err.kind.is_ack() => debug!("could not get cover for '{uri_str}' using {name}: {err}"),
+            Err(err) => return Err(err),
+        }
+    }
+    let Some((data, mime)) = picture else {
+        return Ok(None);
+    };
+
+    let path = dir.join(format!("{:016x}.{}", fnv1a(name), image_extension(mime.as_deref(), &data)));
+
+    fs::create_dir_all(dir).await?;
+    fs::write(&path, &data).await?;
+
+    Ok(Some((path, data.len() as u64)))
+}
+
+/// Returns the 64-bit FNV-1a hash of s. Unlike [std::hash::DefaultHasher], the hash stays the same
+/// across Rust releases, so the names of the cached covers do too
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Reads a picture chunk by chunk using either the readpicture or albumart command.
+/// Returns the picture data and its MIME type if MPD sent it
+async fn read_picture(conn: &mut MPDConnection, cmd: PictureCommand, uri: &str) -> Result<Option<(Vec<u8>, Option<String>)>> {
+    let mut data = Vec::new();
+    let mut mime = None;
+
+    loop {
+        let (res, chunk) = conn.request_binary(&cmd(uri, data.len())).await?;
+
+        // readpicture responds with an empty response if the song has no picture
+        let Some(size) = res
+            .iter()
+            .find(|(k, _)| k == "size")
+            .and_then(|(_, v)| v.parse::<usize>().ok())
+        else {
+            return Ok(None);
+        };
+        if mime.is_none() {
+            mime = res.into_iter().find(|(k, _)| k == "type").map(|(_, v)| v);
+        }
+
+        data.extend_from_slice(&chunk);
+        if chunk.is_empty() || data.len() >= size {
+            break;
+        }
+    }
+
+    Ok(Some((data, mime)))
+}
+
+/// Gets the file extension of an image from its MIME type or by looking at its magic number
+fn image_extension(mime: Option<&str>, data: &[u8]) -> &'static str {
+    match mime {
+        Some("image/jpeg") => "jpg",
+        Some("image/png") => "png",
+        Some("image/webp") => "webp",
+        Some("image/gif") => "gif",
+        Some("image/bmp") => "bmp",
+        _ if data.starts_with(&[0x89, b'P', b'N', b'G']) => "png",
+        _ if data.starts_with(b"GIF8") => "gif",
+        _ if data.starts_with(b"BM") => "bmp",
+        _ if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") => "webp",
+        _ => "jpg",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a("foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,234 @@
+#[cfg(test)]
+mod tests;
+
+use async_std::{fs, io};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Maximum amount of entries in the cache, regardless of their size
+const MAX_ENTRIES: usize = 10_000;
+/// Seconds after which a cache miss is searched again
+const MISS_TTL: u64 = 24 * 60 * 60;
+
+/// A persistent cache mapping songs & albums to their cover.
+/// Records both hits and misses, so that every cover only has to be searched once
+#[derive(Debug, Default)]
+pub struct CoverCache {
+    /// Directory covers fetched from MPD are stored in, alongside the index file
+    dir: PathBuf,
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Path of the cover or None if no cover was found
+    pub cover: Option<PathBuf>,
+    /// File or directory whose modification invalidates this entry
+    pub source: Option<PathBuf>,
+    /// Modification time of source in seconds since the unix epoch
+    pub mtime: Option<u64>,
+    /// Size of the cover in bytes, if it is stored inside the cache directory
+    #[serde(default)]
+    pub size: u64,
+    /// Time the entry was created in seconds since the unix epoch
+    pub created: u64,
+    /// Time the entry was last used in seconds since the unix epoch
+    pub last_used: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    #[serde(default)]
+    entries: HashMap<String, Entry>,
+}
+
+impl CoverCache {
+    /// Loads the cache from the index file in the given directory.
+    /// If the index does not exist or is invalid, an empty cache is returned
+    pub async fn load(dir: PathBuf) -> Self {
+        let index = match fs::read_to_string(dir.join("index.toml")).await {
+            Ok(data) => toml::from_str(&data).unwrap_or_else(|err| {
+                warn!("Cover cache index is invalid, starting with an empty cache: {err}");
+                Index::default()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Index::default(),
+            Err(err) => {
+                warn!("Could not read cover cache index, starting with an empty cache: {err}");
+                Index::default()
+            }
+        };
+
+        Self {
+            dir,
+            entries: index.entries,
+        }
+    }
+
+    /// Writes the index file into the cache directory
+    pub async fn save(&self) -> io::Result<()> {
+        #[derive(Serialize)]
+        struct IndexRef<'a> {
+            entries: &'a HashMap<String, Entry>,
+        }
+
+        let data = toml::to_string(&IndexRef { entries: &self.entries })
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        fs::create_dir_all(&self.dir).await?;
+        // write to a temporary file first, so that the index is never only partially written
+        let tmp = self.dir.join("index.toml.tmp");
+        fs::write(&tmp, data).await?;
+        fs::rename(&tmp, self.dir.join("index.toml")).await
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Looks up the entry with the given key.
+    ///
+    /// Returns Some(cover) if a valid entry exists, where cover is None for a recorded miss.
+    /// Returns None if there is no entry or if it was invalidated.
+    /// Marks a valid entry as used now and removes an invalid one, so the index has to be saved afterwards
+    pub async fn get(&mut self, key: &str) -> Option<Option<PathBuf>> {
+        let entry = self.entries.get(key)?;
+        let now = now();
+
+        if !is_valid(entry, now).await {
+            debug!("cover cache entry for '{key}' is outdated");
+            if let Some(entry) = self.entries.remove(key) {
+                self.remove_file(&entry).await;
+            }
+            return None;
+        }
+
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = now;
+        Some(entry.cover.clone())
+    }
+
+    /// Inserts a new entry into the cache and evicts the least recently used entries
+    /// if the cache grows larger than max_size bytes.
+    ///
+    /// Returns whether the index has to be saved, i.e. false if the same cover was already cached under key
+    /// and nothing was evicted
+    pub async fn insert(
+        &mut self,
+        key: String,
+        cover: Option<PathBuf>,
+        source: Option<PathBuf>,
+        size: u64,
+        max_size: u64,
+    ) -> bool {
+        let now = now();
+        let mtime = match &source {
+            Some(source) => modified(source).await,
+            None => None,
+        };
+
+        let entry = Entry {
+            cover,
+            source,
+            mtime,
+            size,
+            created: now,
+            last_used: now,
+        };
+        let mut changed = true;
+        if let Some(old) = self.entries.insert(key.clone(), entry) {
+            if old.cover != self.entries[&key].cover {
+                self.remove_file(&old).await;
+            } else {
+                // another player found the same cover in the meantime
+                changed = old.source != self.entries[&key].source || old.mtime != self.entries[&key].mtime;
+            }
+        }
+
+        for entry in evict(&mut self.entries, &key, max_size, MAX_ENTRIES) {
+            self.remove_file(&entry).await;
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Removes the cover of an entry from disk, if it is stored inside the cache directory
+    async fn remove_file(&self, entry: &Entry) {
+        if let Some(cover) = &entry.cover
+            && cover.starts_with(&self.dir)
+            && let Err(err) = fs::remove_file(cover).await
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            warn!("Could not remove cached cover `{}`: {err}", cover.display());
+        }
+    }
+}
+
+/// Checks if an entry is still valid, i.e. its cover still exists, its source was not modified
+/// and, in the case of a miss, it has not expired yet
+async fn is_valid(entry: &Entry, now: u64) -> bool {
+    match &entry.cover {
+        Some(cover) => {
+            if !fs::metadata(cover).await.is_ok_and(|m| m.is_file()) {
+                return false;
+            }
+        }
+        None => {
+            if entry.created + MISS_TTL < now {
+                return false;
+            }
+        }
+    }
+
+    match &entry.source {
+        Some(source) => modified(source).await == entry.mtime,
+        None => true,
+    }
+}
+
+/// Removes the least recently used entries until the size of all covers stored in the cache is
+/// at most max_size bytes and there are at most max_entries entries.
+/// The entry with the key keep is never removed.
+///
+/// Returns the removed entries
+fn evict(entries: &mut HashMap<String, Entry>, keep: &str, max_size: u64, max_entries: usize) -> Vec<Entry> {
+    let mut total: u64 = entries.values().map(|e| e.size).sum();
+    if total <= max_size && entries.len() <= max_entries {
+        return Vec::new();
+    }
+
+    let mut by_age: Vec<(u64, String)> = entries
+        .iter()
+        .filter(|(k, _)| *k != keep)
+        .map(|(k, e)| (e.last_used, k.clone()))
+        .collect();
+    by_age.sort_unstable();
+
+    let mut removed = Vec::new();
+    for (_, key) in by_age {
+        if total <= max_size && entries.len() <= max_entries {
+            break;
+        }
+
+        if let Some(entry) = entries.remove(&key) {
+            debug!("evicting '{key}' from the cover cache");
+            total -= entry.size;
+            removed.push(entry);
+        }
+    }
+
+    removed
+}
+
+/// Returns the modification time of a file in seconds since the unix epoch
+async fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).await.ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,54 @@
+use super::*;
+
+fn entry(size: u64, last_used: u64) -> Entry {
+    Entry {
+        cover: None,
+        source: None,
+        mtime: None,
+        size,
+        created: 0,
+        last_used,
+    }
+}
+
+fn entries(list: &[(&str, u64, u64)]) -> HashMap<String, Entry> {
+    list.iter()
+        .map(|(k, size, last_used)| (k.to_string(), entry(*size, *last_used)))
+        .collect()
+}
+
+#[test]
+fn test_no_eviction_below_limits() {
+    let mut map = entries(&[("a", 10, 1), ("b", 10, 2)]);
+    let removed = evict(&mut map, "b", 20, 2);
+
+    assert!(removed.is_empty());
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn test_evict_least_recently_used_by_size() {
+    let mut map = entries(&[("a", 10, 3), ("b", 10, 1), ("c", 10, 2), ("d", 10, 4)]);
+    let removed = evict(&mut map, "d", 20, 10);
+
+    assert_eq!(removed, vec![entry(10, 1), entry(10, 2)]);
+    assert!(map.contains_key("a") && map.contains_key("d"));
+}
+
+#[test]
+fn test_evict_by_entry_count() {
+    let mut map = entries(&[("a", 0, 3), ("b", 0, 1), ("c", 0, 2)]);
+    let removed = evict(&mut map, "c", u64::MAX, 2);
+
+    assert_eq!(removed, vec![entry(0, 1)]);
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn test_evict_keeps_new_entry() {
+    let mut map = entries(&[("a", 10, 5), ("new", 30, 0)]);
+    let removed = evict(&mut map, "new", 20, 10);
+
+    assert_eq!(removed, vec![entry(10, 5)]);
+    assert!(map.contains_key("new"));
+}
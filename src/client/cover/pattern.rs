@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+
+use crate::client::Song;
+use crate::client::placeholder::{placeholders, render_with};
+use crate::util::expand::expand_path;
+
+/// Placeholders that make a pattern only match covers belonging to a single song
+const SONG_PLACEHOLDERS: [&str; 3] = ["filename", "title", "track"];
+
+/// All placeholders that can be used in a cover pattern
+const PLACEHOLDERS: [&str; 12] = [
+    "music_directory",
+    "cover_directory",
+    "directory",
+    "filename",
+    "artist",
+    "albumartist",
+    "album",
+    "title",
+    "genre",
+    "date",
+    "track",
+    "disc",
+];
+
+/// Checks that a pattern only contains known placeholders.
+/// Returns the first unknown placeholder as the error variant
+pub fn validate(pattern: &str) -> Result<(), String> {
+    match placeholders(pattern).find(|p| !PLACEHOLDERS.contains(p)) {
+        Some(p) => Err(p.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Returns true if the pattern only matches covers belonging to a single song
+pub fn is_song_specific(pattern: &str) -> bool {
+    placeholders(pattern).any(|p| SONG_PLACEHOLDERS.contains(&p))
+}
+
+/// Renders a pattern into the path of a cover (without its extension) for the given song.
+///
+/// Returns None if the pattern uses an unknown placeholder, a tag the song does not have
+/// or if the resulting file name would be empty
+pub fn render(pattern: &str, song: &Song, music_dir: &Path, cover_dir: &Path) -> Option<PathBuf> {
+    let pattern = expand_path(pattern);
+    let pattern = pattern.to_str()?;
+    // unclosed braces would be kept as is
+    if placeholders(pattern).count() != pattern.matches('{').count() {
+        return None;
+    }
+
+    let mut complete = true;
+    let ret = render_with(pattern, |name| {
+        let value = value(name, song, music_dir, cover_dir);
+        complete &= value.is_some();
+        value
+    });
+
+    // an empty file name would match files named after their directory, e.g. covers/.jpg
+    if !complete || ret.ends_with('/') || ret.is_empty() {
+        return None;
+    }
+
+    Some(PathBuf::from(ret))
+}
+
+/// Returns the value of the placeholder with the given name for the song,
+/// or None if the placeholder is unknown or the song does not have the tag
+fn value(name: &str, song: &Song, music_dir: &Path, cover_dir: &Path) -> Option<String> {
+    let value = match name {
+        "music_directory" => music_dir.to_string_lossy().into_owned(),
+        "cover_directory" => cover_dir.to_string_lossy().into_owned(),
+        "directory" => song.uri.parent().map_or(String::new(), |p| p.to_string_lossy().into_owned()),
+        "filename" => song.uri.file_stem()?.to_string_lossy().into_owned(),
+        "artist" => sanitize(song.artists.first()?),
+        "albumartist" => sanitize(song.album_artists.first().or(song.artists.first())?),
+        "album" => sanitize(song.album.as_ref()?),
+        "title" => sanitize(song.title.as_ref()?),
+        "genre" => sanitize(song.genres.first()?),
+        "date" => song.date?.to_string(),
+        "track" => song.track?.to_string(),
+        "disc" => song.disc?.to_string(),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Replaces slashes in tag values, so that they don't create new path components
+fn sanitize(tag: &str) -> String {
+    tag.replace('/', "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_COVER_PATTERNS;
+
+    fn render_default(pattern: &str, song: &Song) -> Option<PathBuf> {
+        render(pattern, song, Path::new("/music"), Path::new("/covers"))
+    }
+
+    #[test]
+    fn test_default_patterns() {
+        let song = Song::test_song();
+        let paths: Vec<_> = DEFAULT_COVER_PATTERNS.iter().map(|p| render_default(p, &song)).collect();
+
+        assert_eq!(
+            paths,
+            [
+                Some(PathBuf::from("/covers/Celeste/Resurrections")),
+                Some(PathBuf::from("/covers/Celeste")),
+                Some(PathBuf::from("/music/Celeste/Resurrections")),
+                Some(PathBuf::from("/music/Celeste/cover")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_song_in_root() {
+        let mut song = Song::test_song();
+        song.uri = PathBuf::from("Resurrections.mp3");
+
+        assert_eq!(render_default("{cover_directory}/{directory}", &song), None);
+        assert_eq!(
+            render_default("{cover_directory}/{directory}/{filename}", &song),
+            Some(PathBuf::from("/covers//Resurrections"))
+        );
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut song = Song::test_song();
+        song.album = Some("Celeste/Farewell".into());
+
+        assert_eq!(
+            render_default("{cover_directory}/{albumartist}/{album}", &song),
+            Some(PathBuf::from("/covers/Lena Raine/Celeste_Farewell"))
+        );
+        assert_eq!(
+            render_default("{music_directory}/{directory}/{track} - {title}", &song),
+            Some(PathBuf::from("/music/Celeste/7 - Resurrections"))
+        );
+        assert_eq!(render_default("{music_directory}/{directory}/{genre}", &song), None);
+        assert_eq!(render_default("{music_directory}/{unknown}", &song), None);
+        assert_eq!(render_default("{music_directory}/{unclosed", &song), None);
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(validate("{music_directory}/{directory}/folder"), Ok(()));
+        assert_eq!(validate("{music_directory}/{dir}/folder"), Err(String::from("dir")));
+        assert!(is_song_specific("{cover_directory}/{filename}"));
+        assert!(!is_song_specific("{cover_directory}/{albumartist}/{album}"));
+    }
+}
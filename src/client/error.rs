@@ -85,6 +85,8 @@ pub enum ErrorKind {
     /// Gets returned when MPD does not respond with OK MPD {{VERSION}} while initializing the
     /// connection
     InvalidConnection,
+    /// Gets returned when a request is made while the connection to MPD is lost
+    NotConnected,
     /// Error that occurs when a line from MPD cannot be split into key value pairs
     KeyValueError,
     /// Some other custom error
@@ -112,6 +114,13 @@ impl ErrorKind {
             _ => None,
         }
     }
+
+    /// Returns true if the error was sent by MPD as an ACK response
+    pub fn is_ack(&self) -> bool {
+        use ErrorKind::*;
+
+        !matches!(self, IO | UTF8 | InvalidConnection | NotConnected | KeyValueError | Other)
+    }
 }
 
 impl Display for Error {
@@ -174,6 +183,7 @@ impl From<Error> for zbus::fdo::Error {
             IO => IOError(value.to_string()),
             UTF8 => Failed(value.to_string()),
             InvalidConnection => IOError(value.to_string()),
+            NotConnected => IOError(value.to_string()),
             KeyValueError => Failed(value.to_string()),
             Other => Failed(value.to_string()),
         }
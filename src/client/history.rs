@@ -0,0 +1,177 @@
+use async_std::fs::{self, OpenOptions};
+use async_std::io::WriteExt;
+use log::{debug, warn};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::Song;
+use super::playtime::PlayTime;
+use crate::config::{HistoryFormat, config, label_name};
+use crate::util::get_data_dir;
+
+/// A song is logged once it played this long, even if that is less than half its length
+const MAX_THRESHOLD: Duration = Duration::from_secs(4 * 60);
+/// Songs shorter than this are never logged
+const MIN_LENGTH: Duration = Duration::from_secs(30);
+
+/// Header of a `.scrobbler.log` file, written when the file is created
+const SCROBBLER_HEADER: &str = concat!(
+    "#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/mpdris ",
+    env!("CARGO_PKG_VERSION"),
+    "\n"
+);
+
+/// Checks if a song with the given length played long enough to be logged.
+///
+/// Follows the usual scrobbling rules: the song has to be longer than 30 seconds and must have
+/// been played for half its length or 4 minutes, whichever comes first
+pub fn should_log(length: Option<Duration>, played: Duration) -> bool {
+    let Some(length) = length else {
+        return false;
+    };
+
+    length > MIN_LENGTH && played >= (length / 2).min(MAX_THRESHOLD)
+}
+
+/// Writes the song to the listening history of the player with the given label if it is enabled
+/// and the song played long enough. Returns true if the song does not need to be logged anymore
+pub async fn log(song: &Song, label: Option<&str>, play_time: &PlayTime, now: Instant) -> bool {
+    let Some(format) = config().read().await.history else {
+        return false;
+    };
+    if !should_log(song.duration, play_time.played(now)) {
+        return false;
+    }
+
+    let timestamp = play_time
+        .started()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default())
+        .as_secs();
+
+    match append(format, label, song, timestamp).await {
+        Ok(()) => debug!("wrote '{}' to the listening history", song.uri.display()),
+        Err(err) => warn!("Could not write '{}' to the listening history: {err}", song.uri.display()),
+    }
+
+    true
+}
+
+/// Appends the song to the history file of the player, creating it if necessary
+async fn append(format: HistoryFormat, label: Option<&str>, song: &Song, timestamp: u64) -> std::io::Result<()> {
+    let dir = get_data_dir();
+    fs::create_dir_all(&dir).await?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(file_name(label)))
+        .await?;
+
+    let mut entry = String::new();
+    match format {
+        HistoryFormat::Jsonl => entry.push_str(&json_entry(song, timestamp)),
+        HistoryFormat::Scrobbler => {
+            if file.metadata().await?.len() == 0 {
+                entry.push_str(SCROBBLER_HEADER);
+            }
+            entry.push_str(&scrobbler_entry(song, timestamp));
+        }
+    }
+    entry.push('\n');
+
+    file.write_all(entry.as_bytes()).await?;
+    file.flush().await
+}
+
+/// Returns the name of the history file of the player with the given label.
+/// Each player has its own file, so that the songs of multiple players are not mixed
+fn file_name(label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("history-{}", label_name(label)),
+        None => String::from("history"),
+    }
+}
+
+/// Formats the song as a single line of JSON
+fn json_entry(song: &Song, timestamp: u64) -> String {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "uri": song.uri,
+        "title": song.title.as_deref(),
+        "artist": song.artists.iter().map(AsRef::as_ref).collect::<Vec<&str>>(),
+        "album": song.album.as_deref(),
+        "track": song.track,
+        "duration": song.duration.map(|d| d.as_secs_f64()),
+    })
+    .to_string()
+}
+
+/// Formats the song as a line of a `.scrobbler.log`, which consists of tab separated fields:
+/// artist, album, title, track number, length in seconds, rating, timestamp and MusicBrainz track id
+fn scrobbler_entry(song: &Song, timestamp: u64) -> String {
+    // tabs and newlines would break the format
+    let field = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+    let artists = song.artists.iter().map(|a| field(a)).collect::<Vec<_>>().join(", ");
+
+    [
+        artists,
+        song.album.as_deref().map(field).unwrap_or_default(),
+        song.title.as_deref().map(field).unwrap_or_default(),
+        song.track.map(|t| t.to_string()).unwrap_or_default(),
+        song.duration.map(|d| d.as_secs().to_string()).unwrap_or_default(),
+        // the song was listened to, not skipped
+        String::from("L"),
+        timestamp.to_string(),
+        song.musicbrainz_track_id.as_deref().map(field).unwrap_or_default(),
+    ]
+    .join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn test_should_log() {
+        assert!(!should_log(Some(secs(200)), secs(99)));
+        assert!(should_log(Some(secs(200)), secs(100)));
+        // 4 minutes are enough for long songs
+        assert!(should_log(Some(secs(3600)), secs(240)));
+        assert!(!should_log(Some(secs(3600)), secs(239)));
+    }
+
+    #[test]
+    fn test_should_not_log_short_or_unknown() {
+        assert!(!should_log(Some(secs(30)), secs(30)));
+        assert!(!should_log(None, secs(600)));
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name(None), "history");
+        assert_eq!(file_name(Some("living room")), "history-living_room");
+    }
+
+    #[test]
+    fn test_scrobbler_entry() {
+        let mut song = Song::test_song();
+        song.title = Some("Reach\tfor the Summit".into());
+        song.duration = Some(Duration::from_secs_f64(181.7));
+
+        let expected = "Lena Raine, 2 Mello\tCeleste\tReach for the Summit\t7\t181\tL\t1700000000\t";
+        assert_eq!(scrobbler_entry(&song, 1_700_000_000), expected);
+    }
+
+    #[test]
+    fn test_json_entry() {
+        let entry: serde_json::Value = serde_json::from_str(&json_entry(&Song::test_song(), 5)).unwrap();
+        assert_eq!(entry["uri"], "Celeste/Resurrections.mp3");
+        assert_eq!(entry["artist"][1], "2 Mello");
+        assert_eq!(entry["timestamp"], 5);
+        assert!(entry["duration"].is_null());
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt::Display;
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 use async_std::channel::{Receiver, Sender, bounded, unbounded};
@@ -6,20 +9,43 @@ use async_std::task::{JoinHandle, sleep, spawn};
 
 use futures_util::future::{Either, join, select};
 use futures_util::pin_mut;
-use log::{info, warn};
+use libc::EXIT_FAILURE;
+use log::{debug, error, info, warn};
 
+pub use self::command::{Command, CommandList, Request};
 use self::connection::MPDConnection;
+pub use self::cover::pattern::validate as validate_cover_pattern;
 pub use self::error::MPDResult as Result;
 pub use self::error::*;
-pub use self::status::{PlayState, Repeat, StateChanged, Status};
+pub use self::status::{Output, PlayState, Playlist, Repeat, ReplayGainMode, Song, StateChanged, Status, TriState};
+pub use self::template::{render as render_template, render_status as render_status_template};
+pub use self::template::{validate as validate_template, validate_status as validate_status_template};
 use crate::config::config;
 
+mod command;
 mod connection;
+mod cover;
 mod error;
+mod history;
+mod placeholder;
+mod playtime;
+mod response;
 mod status;
-
-/// Request that gets send when the connection waits for something to happen
-const IDLE_REQUEST: &str = "idle stored_playlist playlist player mixer options";
+mod template;
+
+/// Subsystems the idle connection waits for changes in
+const IDLE_SUBSYSTEMS: [&str; 10] = [
+    "stored_playlist",
+    "playlist",
+    "player",
+    "mixer",
+    "options",
+    "output",
+    "update",
+    "sticker",
+    "message",
+    "partition",
+];
 
 pub struct MPDClient {
     connection: Arc<Mutex<MPDConnection>>,
@@ -28,6 +54,8 @@ pub struct MPDClient {
     /// Cached status
     status: Arc<RwLock<Status>>,
     sender: Sender<StateChanged>,
+    /// Whether the connection to MPD is established. Requests fail immediately while this is false
+    connected: Arc<AtomicBool>,
     #[allow(unused)]
     ping_task: JoinHandle<()>,
     #[allow(unused)]
@@ -35,32 +63,202 @@ pub struct MPDClient {
 }
 
 impl MPDClient {
-    pub async fn request_data(&self, request: &str) -> Result<Vec<(String, String)>> {
+    pub async fn request_data<R: Request>(&self, request: &R) -> Result<R::Response> {
+        self.check_connected()?;
         let mut c = self.connection.lock().await;
 
         c.request_data(request).await
     }
 
+    /// Sends a request whose response contains multiple entities and splits it into one record per entity,
+    /// with key being the first key of each entity. See [response::split_records]
+    pub async fn request_records(&self, request: &Command, key: &str) -> Result<Vec<Vec<(String, String)>>> {
+        Ok(response::split_records(self.request_data(request).await?, key))
+    }
+
     pub async fn reconnect(&self) -> Result<()> {
         let _ = self.drop_idle_lock.send(()).await;
         let (mut c, mut ic) = join(self.connection.lock(), self.idle_connection.lock()).await;
 
-        c.reconnect().await?;
-        ic.reconnect().await?;
+        let result = match c.reconnect().await {
+            Ok(()) => ic.reconnect().await,
+            Err(err) => Err(err),
+        };
+        // the idle task needs both connections to continue
+        drop((c, ic));
         let _ = self.drop_idle_lock.send(()).await;
-        Ok(())
+        result
+    }
+
+    /// Returns true if the connection to MPD is currently established
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    fn check_connected(&self) -> Result<()> {
+        if self.is_connected() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::NotConnected, "Not connected to MPD"))
+        }
     }
 
     /// Play the song with the given id, returns error if the id is invalid
     pub async fn play_song(&self, id: u32) -> Result<()> {
-        let _ = self.request_data(&format!("seekid {id} 0")).await?;
+        let _ = self.request_data(&Command::seekid(id, Duration::ZERO)).await?;
+
+        Ok(())
+    }
+
+    /// Start playback of the song with the given id from its beginning
+    pub async fn goto_song(&self, id: u32) -> Result<()> {
+        let _ = self.request_data(&Command::playid(id)).await?;
+
+        Ok(())
+    }
+
+    /// Returns the ids of all songs in the queue, in the order they are played in
+    pub async fn get_queue(&self) -> Result<Vec<u32>> {
+        let songs = self.request_records(&Command::playlistinfo(), "file").await?;
+
+        Ok(songs
+            .into_iter()
+            .filter_map(|song| song.into_iter().find(|(k, _)| k == "Id"))
+            .filter_map(|(_, v)| v.parse().ok())
+            .collect())
+    }
+
+    /// Returns the song with the given id from the queue, returns error if the id is invalid
+    pub async fn get_song(&self, id: u32) -> Result<Song> {
+        self.check_connected()?;
+        let mut c = self.connection.lock().await;
+        let res = c.request_data(&Command::playlistid(id)).await?;
+
+        Ok(Song::from_response(res, &mut c).await)
+    }
+
+    /// Adds the given uri to the queue at pos or at the end of the queue if pos is None.
+    /// Returns the id of the newly added song
+    pub async fn add_song(&self, uri: &str, pos: Option<u32>) -> Result<u32> {
+        self.request_data(&Command::addid(uri, pos))
+            .await?
+            .into_iter()
+            .find(|(k, _)| k == "Id")
+            .and_then(|(_, v)| v.parse().ok())
+            .ok_or(Error::new(
+                ErrorKind::KeyValueError,
+                "MPD did not respond with the id of the added song",
+            ))
+    }
+
+    /// Sets the sticker with the given name of the song with the given id, returns error if the id is invalid
+    pub async fn set_sticker(&self, id: u32, name: &str, value: impl Display) -> Result<()> {
+        let uri = self
+            .request_data(&Command::playlistid(id))
+            .await?
+            .into_iter()
+            .find(|(k, _)| k == "file")
+            .map(|(_, v)| v)
+            .ok_or(Error::new(
+                ErrorKind::KeyValueError,
+                "MPD did not respond with the uri of the song",
+            ))?;
+        let _ = self.request_data(&Command::sticker_set(&uri, name, value)).await?;
+
+        Ok(())
+    }
+
+    /// Removes the song with the given id from the queue, returns error if the id is invalid
+    pub async fn remove_song(&self, id: u32) -> Result<()> {
+        let _ = self.request_data(&Command::deleteid(id)).await?;
+
+        Ok(())
+    }
+
+    /// Returns all playlists stored by MPD
+    pub async fn get_playlists(&self) -> Result<Vec<Playlist>> {
+        let records = self.request_records(&Command::listplaylists(), "playlist").await?;
+
+        Ok(records
+            .into_iter()
+            .filter_map(|record| {
+                let mut pairs = record.into_iter();
+                // records not starting with a playlist are pairs before the first playlist
+                let (_, name) = pairs.next().filter(|(k, _)| k == "playlist")?;
+                let last_modified = pairs.find(|(k, _)| k == "Last-Modified").map(|(_, v)| v.into());
+
+                Some(Playlist {
+                    name: name.into(),
+                    last_modified,
+                })
+            })
+            .collect())
+    }
+
+    /// Replaces the queue with the stored playlist with the given name and starts playing it
+    pub async fn load_playlist(&self, name: &str) -> Result<()> {
+        let cmd = CommandList::new()
+            .push(Command::clear())
+            .push(Command::load(name))
+            .push(Command::play());
+        let _ = self.request_data(&cmd).await?;
+
+        Ok(())
+    }
+
+    /// Returns all audio outputs of MPD
+    pub async fn get_outputs(&self) -> Result<Vec<Output>> {
+        let records = self.request_records(&Command::outputs(), "outputid").await?;
+
+        Ok(records
+            .into_iter()
+            .filter_map(|record| {
+                let mut pairs = record.into_iter();
+                // records not starting with an id are pairs before the first output
+                let (_, id) = pairs.next().filter(|(k, _)| k == "outputid")?;
+                let mut output = Output {
+                    id: id.parse().ok()?,
+                    name: "".into(),
+                    plugin: "".into(),
+                    enabled: false,
+                };
+
+                for (k, v) in pairs {
+                    match k.as_str() {
+                        "outputname" => output.name = v.into(),
+                        "plugin" => output.plugin = v.into(),
+                        "outputenabled" => output.enabled = v == "1",
+                        _ => {}
+                    }
+                }
+
+                Some(output)
+            })
+            .collect())
+    }
+
+    /// Enables or disables the audio output with the given id, returns error if the id is invalid
+    pub async fn set_output_enabled(&self, id: u32, enabled: bool) -> Result<()> {
+        let cmd = if enabled {
+            Command::enableoutput(id)
+        } else {
+            Command::disableoutput(id)
+        };
+        let _ = self.request_data(&cmd).await?;
+
+        Ok(())
+    }
+
+    /// Enables the audio output with the given id if it is disabled and vice versa
+    pub async fn toggle_output(&self, id: u32) -> Result<()> {
+        let _ = self.request_data(&Command::toggleoutput(id)).await?;
 
         Ok(())
     }
 
     /// Start playback from current song position
     pub async fn play(&self) -> Result<()> {
-        let _ = self.request_data("play").await?;
+        let _ = self.request_data(&Command::play()).await?;
 
         Ok(())
     }
@@ -68,9 +266,7 @@ impl MPDClient {
     /// Seek to time in the current song
     /// To seek relative to the current position use [Self::seek_relative]
     pub async fn seek(&self, time: Duration) -> Result<()> {
-        let _ = self
-            .request_data(&format!("seekcur {}.{}", time.as_secs(), time.subsec_millis()))
-            .await?;
+        let _ = self.request_data(&Command::seekcur(time)).await?;
 
         Ok(())
     }
@@ -78,32 +274,28 @@ impl MPDClient {
     /// Seek to a position in the current song relative to the current position with offset in
     /// To seek from the songs begin (absolute) use [Self::seek]
     pub async fn seek_relative(&self, is_positive: bool, offset: Duration) -> Result<()> {
-        let prefix = if is_positive { '+' } else { '-' };
-
-        let _ = self
-            .request_data(&format!("seekcur {}{}.{}", prefix, offset.as_secs(), offset.subsec_millis()))
-            .await?;
+        let _ = self.request_data(&Command::seekcur_relative(is_positive, offset)).await?;
 
         Ok(())
     }
 
     /// Pause playback
     pub async fn pause(&self) -> Result<()> {
-        let _ = self.request_data("pause 1").await?;
+        let _ = self.request_data(&Command::pause(Some(true))).await?;
 
         Ok(())
     }
 
     /// Stop playback
     pub async fn stop(&self) -> Result<()> {
-        let _ = self.request_data("stop").await?;
+        let _ = self.request_data(&Command::stop()).await?;
 
         Ok(())
     }
 
     /// Toggle playback, e.g. pauses when playing and play when paused
     pub async fn toggle_play(&self) -> Result<()> {
-        let _ = self.request_data("pause").await?;
+        let _ = self.request_data(&Command::pause(None)).await?;
 
         Ok(())
     }
@@ -112,65 +304,109 @@ impl MPDClient {
         Arc::clone(&self.status)
     }
 
-    pub async fn update_status(&self) -> Result<()> {
+    /// Queries the elapsed time of the current song from MPD, see [status::update_elapsed]
+    pub async fn update_elapsed(&self) -> Result<()> {
+        self.check_connected()?;
         let mut s = self.status.write().await;
         let mut conn = self.connection.lock().await;
         let sender = &self.sender;
 
-        status::update_status(&mut conn, &mut s, sender).await?;
+        status::update_elapsed(&mut conn, &mut s, sender).await?;
         Ok(())
     }
 
-    pub async fn new() -> Result<(Self, Receiver<StateChanged>)> {
-        let c = config().read().await;
-
-        info!("Connecting to server on ip-address: {} using port: {}", c.addr, c.port);
-
+    /// Creates a new client which connects to MPD in the background.
+    /// The client connects to the server of the player with the given label, or to the server set in the config if None.
+    /// See [Config::server](crate::config::Config::server).
+    /// Until the connection is established, [Self::is_connected] returns false and all requests fail
+    pub fn new(label: Option<String>) -> (Self, Receiver<StateChanged>) {
         let (sender, recv) = unbounded();
         let status = Arc::new(RwLock::new(Status::new()));
-        let connection = Arc::new(Mutex::new(MPDConnection::new(&c).await?));
-
-        info!("Connecting second stream to ask for updates");
-        let idle_connection = Arc::new(Mutex::new(MPDConnection::new(&c).await?));
+        let connection = Arc::new(Mutex::new(MPDConnection::disconnected(label.clone())));
+        let idle_connection = Arc::new(Mutex::new(MPDConnection::disconnected(label)));
         let (drop_idle_lock, drop_lock) = bounded(1);
 
-        let idle_conn = Arc::clone(&idle_connection);
+        let connected = Arc::new(AtomicBool::new(false));
+
+        let idle_conns = (Arc::clone(&idle_connection), Arc::clone(&connection));
         let idle_sender = Sender::clone(&sender);
         let idle_status = Arc::clone(&status);
+        let idle_connected = Arc::clone(&connected);
         let ping_conn = Arc::clone(&connection);
+        let ping_connected = Arc::clone(&connected);
 
-        let idle_task = spawn(idle_task(idle_conn, idle_status, idle_sender, drop_lock));
-        let ping_task = spawn(ping_task(ping_conn));
+        let idle_task = spawn(idle_task(idle_conns, idle_status, idle_sender, idle_connected, drop_lock));
+        let ping_task = spawn(ping_task(ping_conn, ping_connected));
 
         let client = Self {
             connection,
             idle_connection,
             drop_idle_lock,
             sender,
+            connected,
             ping_task,
             idle_task,
             status,
         };
 
-        client.update_status().await?;
-
-        Ok((client, recv))
+        (client, recv)
     }
 }
 
+/// Connects to MPD, then waits for changes in MPD and updates the status accordingly.
+///
+/// When the connection to MPD is lost, the status is reset and the task waits for MPD to come back
+/// by reconnecting both the idle and the main connection.
+/// If MPD rejects the password or partition, the task stops trying to reconnect until the connections are reconnected
+/// by [MPDClient::reconnect], so that other players keep working.
+/// If this happens on the first connect and this is the only player, the application exits instead
 async fn idle_task(
-    connection: Arc<Mutex<MPDConnection>>,
+    (connection, main_connection): (Arc<Mutex<MPDConnection>>, Arc<Mutex<MPDConnection>>),
     status: Arc<RwLock<Status>>,
     sender: Sender<StateChanged>,
+    connected: Arc<AtomicBool>,
     drop_lock: Receiver<()>,
 ) {
+    let idle = Command::idle(&IDLE_SUBSYSTEMS);
+    let mut was_connected = false;
+
     loop {
+        if !connected.load(Ordering::Relaxed) {
+            let server = connection.lock().await.server().await;
+            info!("Connecting to server on {}", connection::describe(&server));
+            if wait_for_mpd(&connection, &main_connection).await.is_err() {
+                // without any other player, there is nothing left to serve
+                if !was_connected && config().read().await.players().len() <= 1 {
+                    exit(EXIT_FAILURE);
+                }
+
+                warn!("Not trying to reconnect until the config is reloaded");
+                // like while idling, MPDClient::reconnect signals once before and once after reconnecting
+                let _ = drop_lock.recv().await;
+                let _ = drop_lock.recv().await;
+                continue;
+            }
+            was_connected = true;
+
+            info!("Connected to MPD");
+            connected.store(true, Ordering::Relaxed);
+
+            // everything may have changed while disconnected
+            let mut s = status.write().await;
+            if let Err(err) = status::handle_changes(&mut *connection.lock().await, &mut s, &IDLE_SUBSYSTEMS, &sender).await {
+                error!("Could not update status: {err}");
+            }
+            drop(s);
+
+            sender.send(StateChanged::Connection).await.unwrap();
+        }
+
         let mut conn = connection.lock().await;
 
         let result = {
             // we need assign result using coroutine because it is impossible to drop request and therefore the lock on conn
             let result = {
-                let request = conn.request_data(IDLE_REQUEST);
+                let request = conn.request_data(&idle);
                 let drp = drop_lock.recv();
 
                 pin_mut!(request, drp);
@@ -190,35 +426,83 @@ async fn idle_task(
 
         match result {
             Ok(response) => {
-                let mut s = status.write().await;
-
-                match status::update_status(&mut conn, &mut s, &sender).await {
-                    Ok(could_be_seeking) => {
-                        if response[0].1 == "player" && could_be_seeking {
-                            let elapsed = s.elapsed.unwrap().as_micros() as i64;
-                            drop(s);
-
-                            sender.send(StateChanged::Position(elapsed)).await.unwrap();
-                        }
-                    }
-                    Err(err) => {
-                        log::error!("Could not update status: {err}");
+                let mut subsystems: Vec<&str> = Vec::new();
+                for (_, subsystem) in response.iter().filter(|(k, _)| k == "changed") {
+                    if !subsystems.contains(&subsystem.as_str()) {
+                        subsystems.push(subsystem);
                     }
                 }
+
+                let mut s = status.write().await;
+                if let Err(err) = status::handle_changes(&mut conn, &mut s, &subsystems, &sender).await {
+                    error!("Could not handle changes in {subsystems:?}: {err}");
+                }
             }
-            Err(err) => {
+            Err(err) if err.kind.is_ack() => {
                 warn!("Error while awaiting change in MPD: {err}");
-                continue;
             }
+            Err(err) => {
+                warn!("Lost connection to MPD, waiting for it to come back: {err}");
+                connected.store(false, Ordering::Relaxed);
+                status::reset_status(&mut *status.write().await, conn.label(), &sender).await;
+                drop(conn);
+
+                sender.send(StateChanged::Connection).await.unwrap();
+                // the outputs are shown as empty while disconnected
+                sender.send(StateChanged::Outputs).await.unwrap();
+            }
+        }
+    }
+}
+
+/// Tries to (re)connect both connections until MPD is reachable, with increasing delays between attempts.
+/// The connections are only locked during each attempt, so that they can still be reconnected by other means.
+///
+/// ## Errors
+/// - IncorrectPassword if MPD rejects the configured password
+/// - DoesNotExist if the configured partition does not exist
+///
+/// Retrying would not help in both cases, so they are returned immediately
+async fn wait_for_mpd(connection: &Mutex<MPDConnection>, main_connection: &Mutex<MPDConnection>) -> Result<()> {
+    let mut attempt = 0;
+
+    loop {
+        let result = match connection.lock().await.reconnect_with(0).await {
+            Ok(()) => main_connection.lock().await.reconnect_with(0).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => return Ok(()),
+            Err(err) if err.kind == ErrorKind::IncorrectPassword => {
+                error!("MPD rejected the configured password: {err}");
+                return Err(err);
+            }
+            Err(err) if err.kind == ErrorKind::DoesNotExist => {
+                error!("The configured partition does not exist in MPD: {err}");
+                return Err(err);
+            }
+            // only the first failure is logged as a warning to not flood the log while waiting
+            Err(err) if attempt == 0 => warn!("Could not connect to MPD, waiting for it to become reachable: {err}"),
+            Err(err) => debug!("MPD is still unreachable: {err}"),
         }
+
+        sleep(connection::retry_delay(attempt, connection::jitter())).await;
+        attempt = attempt.saturating_add(1);
     }
 }
 
-async fn ping_task(connection: Arc<Mutex<MPDConnection>>) {
+async fn ping_task(connection: Arc<Mutex<MPDConnection>>, connected: Arc<AtomicBool>) {
     loop {
+        // the idle task takes care of reconnecting
+        if !connected.load(Ordering::Relaxed) {
+            sleep(Duration::from_secs(15)).await;
+            continue;
+        }
+
         let mut conn = connection.lock().await;
 
-        match conn.request_data("ping").await {
+        match conn.request_data(&Command::ping()).await {
             Ok(_) => {}
             Err(err) => {
                 warn!("Could not ping MPD: {err}");
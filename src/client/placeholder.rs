@@ -0,0 +1,30 @@
+/// Replaces every placeholder with the value returned by value, keeping it as is if value returns None.
+/// An opening brace without a closing one is kept as is
+pub fn render_with(template: &str, mut value: impl FnMut(&str) -> Option<String>) -> String {
+    let mut rest = template;
+    let mut ret = String::with_capacity(rest.len());
+
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        ret.push_str(&rest[..start]);
+
+        match value(&rest[start + 1..end]) {
+            Some(value) => ret.push_str(&value),
+            None => ret.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    ret.push_str(rest);
+
+    ret
+}
+
+/// Returns an iterator over the names of all placeholders in a template
+pub fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|s| s.split_once('}').map(|(name, _)| name))
+}
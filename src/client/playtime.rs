@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// Measures how long a song has been played, excluding the time it was paused or stopped
+#[derive(Debug, Clone, Default)]
+pub struct PlayTime {
+    /// Time played before playback was last resumed
+    played: Duration,
+    /// When playback was last resumed, or None while not playing
+    resumed: Option<Instant>,
+    /// When the song started playing, or None if it never played
+    started: Option<SystemTime>,
+    /// If the play count of the song was already incremented
+    pub counted: bool,
+    /// If the song was already written to the listening history
+    pub logged: bool,
+}
+
+impl PlayTime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates whether the song is playing since the given point in time
+    pub fn update(&mut self, playing: bool, now: Instant) {
+        match (self.resumed, playing) {
+            (Some(resumed), false) => {
+                self.played += now.saturating_duration_since(resumed);
+                self.resumed = None;
+            }
+            (None, true) => {
+                self.resumed = Some(now);
+                self.started.get_or_insert_with(SystemTime::now);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns when the song started playing, or None if it never played
+    pub fn started(&self) -> Option<SystemTime> {
+        self.started
+    }
+
+    /// Returns how long the song was played until the given point in time
+    pub fn played(&self, now: Instant) -> Duration {
+        self.played + self.resumed.map_or(Duration::ZERO, |r| now.saturating_duration_since(r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pauses_excluded() {
+        let start = Instant::now();
+        let secs = |s| start + Duration::from_secs(s);
+        let mut time = PlayTime::new();
+
+        time.update(true, secs(0));
+        assert_eq!(time.played(secs(10)), Duration::from_secs(10));
+
+        time.update(false, secs(10));
+        assert_eq!(time.played(secs(100)), Duration::from_secs(10));
+
+        time.update(true, secs(100));
+        time.update(true, secs(105));
+        assert_eq!(time.played(secs(110)), Duration::from_secs(20));
+    }
+
+    #[test]
+    fn test_never_played() {
+        let now = Instant::now();
+        let mut time = PlayTime::new();
+
+        time.update(false, now);
+        assert_eq!(time.played(now + Duration::from_secs(60)), Duration::ZERO);
+    }
+}
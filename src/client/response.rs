@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod tests;
+
+/// Separator between the key and value of a response line
+const SEPARATOR: &[u8] = b": ";
+
+/// What a single line of a response from MPD represents
+#[derive(Debug, PartialEq, Eq)]
+pub enum Line {
+    /// `OK`, marking the end of a successful response. Also matches the greeting `OK MPD <version>`
+    Ok,
+    /// `list_OK`, marking the end of the response to a single command in a command list
+    ListOk,
+    /// `ACK ...`, an error response to be parsed using [Error::try_from_mpd()](super::Error::try_from_mpd())
+    Ack(String),
+    /// A key-value pair
+    Pair(String, String),
+    /// A line that is none of the above
+    Invalid,
+}
+
+/// Parses a single line of a response from MPD.
+///
+/// The line is split on the first `": "` only, so values may contain the separator themselves.
+/// Invalid UTF-8 gets replaced, since MPD passes through e.g. file names and tags as is
+pub fn parse_line(line: &[u8]) -> Line {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+
+    if line == b"OK" || line.starts_with(b"OK MPD ") {
+        return Line::Ok;
+    } else if line == b"list_OK" {
+        return Line::ListOk;
+    } else if line.starts_with(b"ACK ") {
+        return Line::Ack(String::from_utf8_lossy(line).into_owned());
+    }
+
+    let Some(idx) = line.windows(SEPARATOR.len()).position(|w| w == SEPARATOR) else {
+        return Line::Invalid;
+    };
+    let (k, v) = (&line[..idx], &line[idx + SEPARATOR.len()..]);
+
+    // keys are never empty and never contain whitespace
+    if k.is_empty() || k.iter().any(u8::is_ascii_whitespace) {
+        return Line::Invalid;
+    }
+
+    Line::Pair(
+        String::from_utf8_lossy(k).into_owned(),
+        String::from_utf8_lossy(v).into_owned(),
+    )
+}
+
+/// Splits a response containing multiple entities into one record per entity.
+///
+/// A new record starts on each occurrence of key, e.g. `file` for `playlistinfo`,
+/// `playlist` for `listplaylists` or `outputid` for `outputs`.
+/// Pairs before the first occurrence of key form a record of their own
+pub fn split_records(pairs: Vec<(String, String)>, key: &str) -> Vec<Vec<(String, String)>> {
+    let mut records: Vec<Vec<(String, String)>> = Vec::new();
+
+    for pair in pairs {
+        match records.last_mut() {
+            Some(record) if pair.0 != key => record.push(pair),
+            _ => records.push(vec![pair]),
+        }
+    }
+
+    records
+}
@@ -0,0 +1,122 @@
+use super::*;
+
+fn pair(k: &str, v: &str) -> (String, String) {
+    (String::from(k), String::from(v))
+}
+
+#[test]
+fn test_simple_pair() {
+    let result = parse_line(b"volume: 50\n");
+    let expected = Line::Pair(String::from("volume"), String::from("50"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_value_containing_separator() {
+    let result = parse_line(b"Title: Act I: Overture\n");
+    let expected = Line::Pair(String::from("Title"), String::from("Act I: Overture"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_empty_value() {
+    let result = parse_line(b"Title: \n");
+    let expected = Line::Pair(String::from("Title"), String::new());
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_value_whitespace_kept() {
+    let result = parse_line(b"Title:  padded \n");
+    let expected = Line::Pair(String::from("Title"), String::from(" padded "));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_invalid_utf8() {
+    let result = parse_line(b"file: music/\xffsong.mp3\n");
+    let expected = Line::Pair(String::from("file"), String::from("music/\u{fffd}song.mp3"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_ok() {
+    assert_eq!(parse_line(b"OK\n"), Line::Ok);
+    assert_eq!(parse_line(b"OK MPD 0.23.5\n"), Line::Ok);
+    assert_eq!(parse_line(b"list_OK\n"), Line::ListOk);
+}
+
+#[test]
+fn test_ack() {
+    let result = parse_line(b"ACK [50@0] {load} No such playlist\n");
+    let expected = Line::Ack(String::from("ACK [50@0] {load} No such playlist"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_invalid_lines() {
+    assert_eq!(parse_line(b"no separator\n"), Line::Invalid);
+    assert_eq!(parse_line(b": no key\n"), Line::Invalid);
+    assert_eq!(parse_line(b"a key: value\n"), Line::Invalid);
+    assert_eq!(parse_line(b"OKAY\n"), Line::Invalid);
+}
+
+#[test]
+fn test_split_playlistinfo() {
+    let response = vec![
+        pair("file", "a.mp3"),
+        pair("Title", "A"),
+        pair("Id", "1"),
+        pair("file", "b.mp3"),
+        pair("Id", "2"),
+    ];
+    let expected = vec![
+        vec![pair("file", "a.mp3"), pair("Title", "A"), pair("Id", "1")],
+        vec![pair("file", "b.mp3"), pair("Id", "2")],
+    ];
+
+    assert_eq!(split_records(response, "file"), expected);
+}
+
+#[test]
+fn test_split_outputs() {
+    let response = vec![
+        pair("outputid", "0"),
+        pair("outputname", "Pipewire"),
+        pair("attribute", "dop=0"),
+        pair("outputid", "1"),
+        pair("outputname", "FIFO"),
+    ];
+    let expected = vec![
+        vec![
+            pair("outputid", "0"),
+            pair("outputname", "Pipewire"),
+            pair("attribute", "dop=0"),
+        ],
+        vec![pair("outputid", "1"), pair("outputname", "FIFO")],
+    ];
+
+    assert_eq!(split_records(response, "outputid"), expected);
+}
+
+#[test]
+fn test_split_leading_pairs() {
+    let response = vec![pair("directory", "a"), pair("playlist", "fav"), pair("Last-Modified", "2024")];
+    let expected = vec![
+        vec![pair("directory", "a")],
+        vec![pair("playlist", "fav"), pair("Last-Modified", "2024")],
+    ];
+
+    assert_eq!(split_records(response, "playlist"), expected);
+}
+
+#[test]
+fn test_split_empty() {
+    assert_eq!(split_records(Vec::new(), "file"), Vec::<Vec<(String, String)>>::new());
+}
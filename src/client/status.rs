@@ -1,24 +1,36 @@
 use async_std::channel::Sender;
-use log::debug;
-use std::{mem::replace, path::PathBuf, sync::Arc, time::Duration};
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{mem::replace, str::FromStr, sync::Arc};
 
-use crate::config::config;
-
-use super::MPDConnection;
 use super::MPDResult;
-
-const IMG_EXTS: [&str; 10] = ["jpg", "jpeg", "png", "webp", "avif", "jxl", "bmp", "gif", "heif", "heic"];
+use super::cover;
+use super::history;
+use super::playtime::PlayTime;
+use super::{Command, MPDConnection};
+use crate::config::config;
 
 #[derive(Debug, Clone)]
 pub struct Status {
     /// The play state of MPD. See: [PlayState]
     pub state: PlayState,
-    /// The Volume MPD outputs in percent
-    pub volume: u8,
+    /// The Volume MPD outputs in percent, or None if MPD has no mixer
+    pub volume: Option<u8>,
     /// Repeat behaviour of MPD. See: [Repeat]
     pub repeat: Repeat,
     /// If shuffling is turned on
     pub shuffle: bool,
+    /// If MPD stops after the current song, or repeats it if [Self::repeat] is on. See: [TriState]
+    pub single: TriState,
+    /// If songs are removed from the queue after they were played. See: [TriState]
+    pub consume: TriState,
+    /// The crossfade between songs in seconds, 0 if disabled
+    pub crossfade: u32,
+    /// The volume threshold in decibels used by MixRamp
+    pub mixramp_db: f64,
+    /// The replay gain mode. See: [ReplayGainMode]
+    pub replay_gain_mode: ReplayGainMode,
     /// elapsed time of the current song, or None if no song selected
     pub elapsed: Option<Duration>,
     /// Duration of the current song, or None if no song selected
@@ -29,20 +41,31 @@ pub struct Status {
     pub next_song: Option<u32>,
     /// The length of the current playlist/tracklist
     pub playlist_length: u32,
+    /// If MPD is currently updating its database
+    pub updating_db: bool,
+    /// How long the current song has been played
+    pub play_time: PlayTime,
 }
 
 impl Status {
     pub fn new() -> Self {
         Self {
-            state: PlayState::Paused,
-            volume: 100,
+            state: PlayState::Stopped,
+            volume: None,
             repeat: Repeat::Off,
             shuffle: false,
+            single: TriState::Off,
+            consume: TriState::Off,
+            crossfade: 0,
+            mixramp_db: 0.0,
+            replay_gain_mode: ReplayGainMode::Off,
             elapsed: None,
             duration: None,
             current_song: None,
             next_song: None,
             playlist_length: 0,
+            updating_db: false,
+            play_time: PlayTime::new(),
         }
     }
 }
@@ -54,6 +77,17 @@ pub enum PlayState {
     Stopped,
 }
 
+impl PlayState {
+    /// Returns the lowercase name of the state, e.g. `playing`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Playing => "playing",
+            Self::Paused => "paused",
+            Self::Stopped => "stopped",
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Repeat {
     Off = 0,
@@ -61,6 +95,71 @@ pub enum Repeat {
     Single = 2,
 }
 
+/// State of an option that can also be enabled for the current song only, like single & consume
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TriState {
+    Off,
+    On,
+    OneShot,
+}
+
+impl TriState {
+    /// Parses the value MPD reports in its status, e.g. `0`, `1` or `oneshot`
+    fn from_mpd(value: &str) -> Self {
+        match value {
+            "1" => Self::On,
+            "oneshot" => Self::OneShot,
+            _ => Self::Off,
+        }
+    }
+
+    /// Returns the value MPD expects as argument
+    pub fn to_mpd(self) -> &'static str {
+        match self {
+            Self::Off => "0",
+            Self::On => "1",
+            Self::OneShot => "oneshot",
+        }
+    }
+}
+
+impl From<bool> for TriState {
+    fn from(value: bool) -> Self {
+        if value { Self::On } else { Self::Off }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ReplayGainMode {
+    Off,
+    Track,
+    Album,
+    Auto,
+}
+
+impl ReplayGainMode {
+    /// Parses the mode as MPD names it, e.g. `track`
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "off" => Some(Self::Off),
+            "track" => Some(Self::Track),
+            "album" => Some(Self::Album),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+
+    /// Returns the name MPD uses for the mode
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Track => "track",
+            Self::Album => "album",
+            Self::Auto => "auto",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Song {
     pub uri: PathBuf,
@@ -75,7 +174,13 @@ pub struct Song {
     pub composers: Vec<Arc<str>>,
     pub comments: Vec<Arc<str>>,
     pub disc: Option<u8>,
+    pub duration: Option<Duration>,
     pub id: u32,
+    pub musicbrainz_track_id: Option<Arc<str>>,
+    /// The value of the rating sticker, see [Config::rating_sticker](crate::config::Config::rating_sticker)
+    pub rating: Option<u32>,
+    /// The value of the play count sticker, see [Config::play_count_sticker](crate::config::Config::play_count_sticker)
+    pub play_count: Option<u32>,
 }
 
 impl Eq for Song {}
@@ -101,52 +206,35 @@ impl Song {
             composers: Vec::new(),
             comments: Vec::new(),
             disc: None,
+            duration: None,
             id: 0,
+            musicbrainz_track_id: None,
+            rating: None,
+            play_count: None,
         }
     }
 
-    async fn try_set_cover_url(&mut self) {
+    async fn try_set_cover_url(&mut self, conn: &mut MPDConnection) {
         debug!("searching cover for '{}'", self.uri.display());
 
-        let paths = {
-            let mut vec = Vec::new();
-            let c = config().read().await;
-
-            vec.push(c.cover_directory.join(&*self.uri));
-            // Music/Celeste/Resurrections.mp3 -> covers/Celeste
-            if let Some(p) = self.uri.parent()
-                && p.parent().is_some()
-            {
-                // p.parent() is none if p = ""
-                vec.push(c.cover_directory.join(p));
-            }
-
-            vec.push(c.music_directory.join(&*self.uri));
-            vec.push(vec[vec.len() - 1].with_file_name("cover"));
+        match cover::find_cover(self, conn).await {
+            Some(path) => self.cover = Some(format!("file://{}", path.display()).into()),
+            None => debug!("no cover found"),
+        }
+    }
 
-            vec
+    /// Reads the rating & play count of the song from its stickers
+    pub async fn load_stickers(&mut self, conn: &mut MPDConnection) {
+        let (rating, play_count) = {
+            let c = config().read().await;
+            (c.rating_sticker.clone(), c.play_count_sticker.clone())
         };
 
-        for mut path in paths {
-            debug!("searching path '{}' for cover", path.display());
-
-            for ext in IMG_EXTS {
-                path.set_extension(ext);
-                if !path.is_file() {
-                    continue;
-                }
-
-                let path = path.display();
-                debug!("found cover '{path}'");
-                self.cover = Some(format!("file://{path}").into());
-                return;
-            }
-        }
-
-        debug!("no cover found");
+        self.rating = get_sticker(conn, &self.uri, &rating).await;
+        self.play_count = get_sticker(conn, &self.uri, &play_count).await;
     }
 
-    async fn from_response(value: Vec<(String, String)>) -> Self {
+    pub async fn from_response(value: Vec<(String, String)>, conn: &mut MPDConnection) -> Self {
         let mut song = Self::new();
 
         for (k, v) in value {
@@ -162,37 +250,191 @@ impl Song {
                 "Composer" => song.composers.push(v.into()),
                 "Comment" => song.comments.push(v.into()),
                 "Disc" => song.disc = v.parse().ok(),
+                "duration" => song.duration = v.parse().ok().map(Duration::from_secs_f64),
                 "Id" => song.id = v.parse().unwrap_or(0),
+                "MUSICBRAINZ_TRACKID" => song.musicbrainz_track_id = Some(v.into()),
                 &_ => {}
             }
         }
-        song.try_set_cover_url().await;
+        song.try_set_cover_url(conn).await;
+        song.load_stickers(conn).await;
 
         song
     }
 }
 
+/// Returns the value of the sticker with the given name of the song with the given uri,
+/// or None if the name is empty or the song has no such sticker
+async fn get_sticker<T: FromStr>(conn: &mut MPDConnection, uri: &Path, name: &str) -> Option<T> {
+    if name.is_empty() {
+        return None;
+    }
+
+    let res = match conn.request_data(&Command::sticker_get(&uri.to_string_lossy(), name)).await {
+        Ok(res) => res,
+        // the song has no such sticker or MPD has no sticker database
+        Err(err) => {
+            debug!("could not get sticker '{name}' of '{}': {err}", uri.display());
+            return None;
+        }
+    };
+
+    // MPD responds with sticker: <name>=<value>
+    let (_, value) = res.into_iter().find(|(k, _)| k == "sticker")?;
+    value.strip_prefix(name)?.strip_prefix('=')?.parse().ok()
+}
+
+/// A playlist stored by MPD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Playlist {
+    pub name: Arc<str>,
+    /// Time of the last modification in the ISO 8601 format
+    pub last_modified: Option<Arc<str>>,
+}
+
+/// An audio output of MPD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub id: u32,
+    pub name: Arc<str>,
+    /// The plugin used by the output, e.g. `pipewire` or `httpd`
+    pub plugin: Arc<str>,
+    pub enabled: bool,
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum StateChanged {
     Position(i64),
     Song(bool, bool),
     Playlist,
+    StoredPlaylists,
     PlayState,
     Volume,
+    /// MPD gained or lost its mixer, which decides whether the volume can be changed
+    Mixer,
     Repeat,
     Shuffle,
+    /// The connection to MPD was established or lost
+    Connection,
+    /// An audio output was added, removed, enabled or disabled
+    Outputs,
+    /// MPD started or finished updating its database
+    DatabaseUpdating,
+    /// A sticker was added, changed or removed
+    Sticker,
+    /// A message was received on a subscribed channel
+    Message,
+    /// A partition was added, removed or changed
+    Partition,
+    Single,
+    Consume,
+    Crossfade,
+    MixRampDb,
+    ReplayGainMode,
 }
 
-/// Updates the given status with new information from MPD gathered from the given connection.
-/// Returns a boolean if the query was successful, or the Error variant
-/// if there was an error with the communication with MPD.
-/// Boolean is true when MPD was previously playing and still is, and the current song hasn't changed
-pub async fn update_status(conn: &mut MPDConnection, status: &mut Status, sender: &Sender<StateChanged>) -> MPDResult<bool> {
-    let res = conn.request_data("status").await?;
-    let mut old_status = replace(status, Status::new());
+/// Subsystems whose changes are reflected in the response to `status`
+const STATUS_SUBSYSTEMS: [&str; 5] = ["player", "mixer", "options", "playlist", "update"];
+
+/// Updates the elapsed time of the current song with new information from MPD gathered from the given connection
+/// and sends a [StateChanged::Position] if it changed while not playing.
+///
+/// Everything else is left to [handle_changes], so that a change of the song is always counted in its play time
+pub async fn update_elapsed(conn: &mut MPDConnection, status: &mut Status, sender: &Sender<StateChanged>) -> MPDResult<()> {
+    let (new, song_id) = query_status(conn).await?;
+
+    if take_elapsed(status, &new, song_id) && status.state != PlayState::Playing {
+        let elapsed = status.elapsed.unwrap_or_default().as_micros() as i64;
+        sender.send(StateChanged::Position(elapsed)).await.unwrap();
+    }
+    Ok(())
+}
+
+/// Takes the elapsed time from the new status if it still belongs to the current song.
+/// Returns true if the elapsed time changed
+fn take_elapsed(status: &mut Status, new: &Status, song_id: Option<u32>) -> bool {
+    if status.current_song.as_ref().map(|s| s.id) != song_id || status.elapsed == new.elapsed {
+        return false;
+    }
+
+    status.elapsed = new.elapsed;
+    true
+}
+
+/// Handles the subsystems MPD reported as changed in response to `idle`.
+///
+/// Each subsystem only updates its part of the status, e.g. a change in `mixer` only updates the volume,
+/// and the current song is only queried again when `player` changed
+pub async fn handle_changes(
+    conn: &mut MPDConnection,
+    status: &mut Status,
+    subsystems: &[&str],
+    sender: &Sender<StateChanged>,
+) -> MPDResult<()> {
+    let new = match subsystems.iter().any(|s| STATUS_SUBSYSTEMS.contains(s)) {
+        true => Some(query_status(conn).await?),
+        false => None,
+    };
+    let old_status = status.clone();
 
-    let mut is_single = false;
     let mut song_changed = false;
+    let mut queue_changed = false;
+    let mut seeked = false;
+
+    for subsystem in subsystems {
+        match (*subsystem, &new) {
+            ("player", Some((new, song_id))) => {
+                song_changed = update_player(conn, status, new, *song_id).await?;
+                update_play_time(conn, status, old_status.current_song.as_ref(), song_changed).await;
+                // a change in player without any visible change while playing means MPD seeked
+                seeked = !song_changed && old_status.state == PlayState::Playing && status.state == PlayState::Playing;
+            }
+            ("mixer", Some((new, _))) => update_volume(status, new),
+            ("options", Some((new, _))) => {
+                update_options(status, new);
+                update_replay_gain(conn, status).await?;
+            }
+            ("playlist", Some((new, _))) => {
+                update_queue(status, new);
+                // the queue can change without its length changing, e.g. when moving songs
+                queue_changed = true;
+            }
+            ("update", Some((new, _))) => update_database(status, new),
+            ("stored_playlist", _) => sender.send(StateChanged::StoredPlaylists).await.unwrap(),
+            ("output", _) => sender.send(StateChanged::Outputs).await.unwrap(),
+            ("sticker", _) => {
+                if let Some(song) = &mut status.current_song {
+                    song.load_stickers(conn).await;
+                }
+                sender.send(StateChanged::Sticker).await.unwrap();
+            }
+            ("message", _) => sender.send(StateChanged::Message).await.unwrap(),
+            ("partition", _) => sender.send(StateChanged::Partition).await.unwrap(),
+            (subsystem, _) => debug!("Ignoring change in subsystem '{subsystem}'"),
+        }
+    }
+
+    send_changes(&old_status, status, song_changed, queue_changed, sender).await;
+    if seeked {
+        let elapsed = status.elapsed.unwrap_or_default().as_micros() as i64;
+        sender.send(StateChanged::Position(elapsed)).await.unwrap();
+    }
+
+    Ok(())
+}
+
+/// Queries the status of MPD. Returns the status without the current song, and the id of the current song
+async fn query_status(conn: &mut MPDConnection) -> MPDResult<(Status, Option<u32>)> {
+    let res = conn.request_data(&Command::status()).await?;
+
+    Ok(parse_status(res))
+}
+
+/// Parses the response to `status`. Returns the status without the current song, and the id of the current song
+fn parse_status(res: Vec<(String, String)>) -> (Status, Option<u32>) {
+    let mut status = Status::new();
+
+    let mut song_id = None;
 
     for (k, v) in res {
         match k.as_str() {
@@ -202,11 +444,11 @@ pub async fn update_status(conn: &mut MPDConnection, status: &mut Status, sender
                 "stop" => status.state = PlayState::Stopped,
                 _ => {}
             },
-            "single" => {
-                if v.parse().unwrap_or(0) > 0 {
-                    is_single = true;
-                }
-            }
+            "single" => status.single = TriState::from_mpd(&v),
+            "consume" => status.consume = TriState::from_mpd(&v),
+            // only present if crossfade is enabled
+            "xfade" => status.crossfade = v.parse().unwrap_or(0),
+            "mixrampdb" => status.mixramp_db = v.parse().unwrap_or(0.0),
             "repeat" => {
                 if v.parse().unwrap_or(0) > 0 {
                     status.repeat = Repeat::On;
@@ -214,46 +456,173 @@ pub async fn update_status(conn: &mut MPDConnection, status: &mut Status, sender
                     status.repeat = Repeat::Off;
                 }
             }
-            "duration" => {
-                if let Ok(secs) = v.parse::<f64>() {
-                    status.duration = Some(Duration::from_secs_f64(secs));
-                } else {
-                    status.duration = None;
-                }
-            }
-            "elapsed" => {
-                if let Ok(secs) = v.parse::<f64>() {
-                    status.elapsed = Some(Duration::from_secs_f64(secs));
-                } else {
-                    status.elapsed = None;
-                }
-            }
-            "songid" => {
-                let id = v.parse().unwrap_or(u32::MAX);
-                let old_id = old_status.current_song.as_ref().map_or_else(|| u32::MIN, |s| s.id);
-
-                if id != old_id {
-                    status.current_song = Some(Song::from_response(conn.request_data("currentsong").await?).await);
-                    song_changed = true;
-                } else {
-                    status.current_song = old_status.current_song.take();
-                }
-            }
-            "volume" => status.volume = v.parse().unwrap_or(0),
+            "duration" => status.duration = v.parse().ok().map(Duration::from_secs_f64),
+            "elapsed" => status.elapsed = v.parse().ok().map(Duration::from_secs_f64),
+            "songid" => song_id = v.parse().ok(),
+            // MPD reports a volume of -1 if there is no mixer
+            "volume" => status.volume = v.parse::<u8>().ok().map(|v| v.min(100)),
             "random" => status.shuffle = v.parse().unwrap_or(0) > 0,
             "nextsongid" => status.next_song = v.parse().ok(),
             "playlistlength" => status.playlist_length = v.parse().unwrap_or(0),
+            // only present while the database is being updated
+            "updating_db" => status.updating_db = true,
             _ => {}
         }
     }
 
-    if is_single {
+    if status.single == TriState::On {
         status.repeat = Repeat::Single;
     }
 
+    (status, song_id)
+}
+
+/// Handles a change in the `player` subsystem. Queries the current song only if it changed.
+/// Returns true if the current song changed
+async fn update_player(conn: &mut MPDConnection, status: &mut Status, new: &Status, song_id: Option<u32>) -> MPDResult<bool> {
+    status.state = new.state;
+    status.elapsed = new.elapsed;
+    status.duration = new.duration;
+    status.next_song = new.next_song;
+
+    if status.current_song.as_ref().map(|s| s.id) == song_id {
+        return Ok(false);
+    }
+
+    status.current_song = match song_id {
+        Some(_) => {
+            let res = conn.request_data(&Command::currentsong()).await?;
+            Some(Song::from_response(res, conn).await)
+        }
+        None => None,
+    };
+    Ok(true)
+}
+
+/// Tracks how long the current song has been played. Once a song was played long enough,
+/// its play count is incremented and it is written to the listening history
+async fn update_play_time(conn: &mut MPDConnection, status: &mut Status, old_song: Option<&Song>, song_changed: bool) {
+    let now = Instant::now();
+
+    if song_changed {
+        // the old song stopped playing now
+        status.play_time.update(false, now);
+        if let Some(song) = old_song {
+            track_play(conn, song, &mut status.play_time, now).await;
+        }
+
+        status.play_time = PlayTime::new();
+    }
+
+    status.play_time.update(status.state == PlayState::Playing, now);
+    if let Some(song) = &status.current_song {
+        track_play(conn, song, &mut status.play_time, now).await;
+    }
+}
+
+/// Increments the play count of the song and writes it to the listening history, each once it was played long enough
+async fn track_play(conn: &mut MPDConnection, song: &Song, play_time: &mut PlayTime, now: Instant) {
+    if !play_time.counted {
+        play_time.counted = count_play(conn, song, play_time, now).await;
+    }
+    if !play_time.logged {
+        play_time.logged = history::log(song, conn.label(), play_time, now).await;
+    }
+}
+
+/// Increments the play count of the song if counting plays is enabled and it was played past the threshold.
+/// Returns true if the song was played past the threshold, so that it is only counted once
+async fn count_play(conn: &mut MPDConnection, song: &Song, play_time: &PlayTime, now: Instant) -> bool {
+    let (sticker, threshold) = {
+        let c = config().read().await;
+        if !c.count_plays || c.play_count_sticker.is_empty() {
+            return false;
+        }
+        (c.play_count_sticker.clone(), f64::from(c.play_count_threshold) / 100.0)
+    };
+
+    let Some(duration) = song.duration else {
+        return false;
+    };
+    if play_time.played(now) < duration.mul_f64(threshold) {
+        return false;
+    }
+
+    let count = song.play_count.unwrap_or(0).saturating_add(1);
+    let cmd = Command::sticker_set(&song.uri.to_string_lossy(), &sticker, count);
+    match conn.request_data(&cmd).await {
+        Ok(_) => debug!("incremented play count of '{}' to {count}", song.uri.display()),
+        Err(err) => warn!("Could not increment the play count of '{}': {err}", song.uri.display()),
+    }
+
+    true
+}
+
+/// Handles a change in the `mixer` subsystem
+fn update_volume(status: &mut Status, new: &Status) {
+    status.volume = new.volume;
+}
+
+/// Handles a change in the `options` subsystem, except for the replay gain mode. See [update_replay_gain]
+fn update_options(status: &mut Status, new: &Status) {
+    status.repeat = new.repeat;
+    status.shuffle = new.shuffle;
+    status.single = new.single;
+    status.consume = new.consume;
+    status.crossfade = new.crossfade;
+    status.mixramp_db = new.mixramp_db;
+}
+
+/// Queries the replay gain mode, which is not part of the response to `status`
+async fn update_replay_gain(conn: &mut MPDConnection, status: &mut Status) -> MPDResult<()> {
+    let res = conn.request_data(&Command::replay_gain_status()).await?;
+
+    if let Some((_, mode)) = res.iter().find(|(k, _)| k == "replay_gain_mode") {
+        status.replay_gain_mode = ReplayGainMode::parse(mode).unwrap_or(ReplayGainMode::Off);
+    }
+    Ok(())
+}
+
+/// Handles a change in the `playlist` subsystem, i.e. the queue
+fn update_queue(status: &mut Status, new: &Status) {
+    status.playlist_length = new.playlist_length;
+    status.next_song = new.next_song;
+}
+
+/// Handles a change in the `update` subsystem
+fn update_database(status: &mut Status, new: &Status) {
+    status.updating_db = new.updating_db;
+}
+
+/// Resets the given status to that of a stopped player with an empty queue and sends the resulting changes.
+/// Used when the connection to MPD of the player with the given label is lost.
+///
+/// The current song is written to the listening history if it played long enough,
+/// as it would otherwise be lost once MPD comes back
+pub async fn reset_status(status: &mut Status, label: Option<&str>, sender: &Sender<StateChanged>) {
+    let now = Instant::now();
+    status.play_time.update(false, now);
+    if let Some(song) = status.current_song.as_ref().filter(|_| !status.play_time.logged) {
+        status.play_time.logged = history::log(song, label, &status.play_time, now).await;
+    }
+
+    let old_status = replace(status, Status::new());
+    let song_changed = old_status.current_song.is_some();
+
+    send_changes(&old_status, status, song_changed, false, sender).await;
+}
+
+/// Sends a [StateChanged] for everything that differs between the old and the new status
+async fn send_changes(
+    old_status: &Status,
+    status: &Status,
+    song_changed: bool,
+    queue_changed: bool,
+    sender: &Sender<StateChanged>,
+) {
     if old_status.state != PlayState::Playing && status.state != PlayState::Playing && old_status.elapsed != status.elapsed {
         #[rustfmt::skip]
-        sender.send(StateChanged::Position(status.elapsed.unwrap().as_micros() as i64)).await.unwrap();
+        sender.send(StateChanged::Position(status.elapsed.unwrap_or_default().as_micros() as i64)).await.unwrap();
     }
     if old_status.state != status.state {
         sender.send(StateChanged::PlayState).await.unwrap();
@@ -261,24 +630,100 @@ pub async fn update_status(conn: &mut MPDConnection, status: &mut Status, sender
     if old_status.volume != status.volume {
         sender.send(StateChanged::Volume).await.unwrap();
     }
+    if old_status.volume.is_some() != status.volume.is_some() {
+        sender.send(StateChanged::Mixer).await.unwrap();
+    }
     if old_status.repeat != status.repeat {
         sender.send(StateChanged::Repeat).await.unwrap();
     }
     if old_status.shuffle != status.shuffle {
         sender.send(StateChanged::Shuffle).await.unwrap();
     }
+    if old_status.single != status.single {
+        sender.send(StateChanged::Single).await.unwrap();
+    }
+    if old_status.consume != status.consume {
+        sender.send(StateChanged::Consume).await.unwrap();
+    }
+    if old_status.crossfade != status.crossfade {
+        sender.send(StateChanged::Crossfade).await.unwrap();
+    }
+    if old_status.mixramp_db != status.mixramp_db {
+        sender.send(StateChanged::MixRampDb).await.unwrap();
+    }
+    if old_status.replay_gain_mode != status.replay_gain_mode {
+        sender.send(StateChanged::ReplayGainMode).await.unwrap();
+    }
     if song_changed {
         let prev = old_status.playlist_length != status.playlist_length
             && ((status.playlist_length < 1) != (old_status.playlist_length < 1));
         let next = old_status.next_song != status.next_song;
         sender.send(StateChanged::Song(prev, next)).await.unwrap();
     }
-    if old_status.next_song.is_some() != status.next_song.is_some() || old_status.playlist_length != status.playlist_length {
+    if old_status.updating_db != status.updating_db {
+        sender.send(StateChanged::DatabaseUpdating).await.unwrap();
+    }
+    if queue_changed
+        || old_status.next_song.is_some() != status.next_song.is_some()
+        || old_status.playlist_length != status.playlist_length
+    {
         sender.send(StateChanged::Playlist).await.unwrap();
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Song {
+        /// Creates a song with a file, title, artists, album and track number, shared by the tests of all modules
+        pub fn test_song() -> Self {
+            let mut song = Self::new();
+            song.uri = PathBuf::from("Celeste/Resurrections.mp3");
+            song.title = Some("Resurrections".into());
+            song.artists = vec!["Lena Raine".into(), "2 Mello".into()];
+            song.album = Some("Celeste".into());
+            song.track = Some(7);
+            song
+        }
+    }
+
+    #[test]
+    fn test_take_elapsed() {
+        let mut song = Song::test_song();
+        song.id = 1;
+        let mut status = Status::new();
+        status.current_song = Some(song);
+        status.play_time.counted = true;
+
+        let mut new = Status::new();
+        new.elapsed = Some(Duration::from_secs(3));
+        assert!(take_elapsed(&mut status, &new, Some(1)));
+        assert_eq!(status.elapsed, Some(Duration::from_secs(3)));
+
+        // the song changed before the idle task noticed, which has to handle it to count the play of the old song
+        new.elapsed = Some(Duration::from_secs(1));
+        assert!(!take_elapsed(&mut status, &new, Some(2)));
+        assert_eq!(status.current_song.as_ref().map(|s| s.id), Some(1));
+        assert_eq!(status.elapsed, Some(Duration::from_secs(3)));
+        assert!(status.play_time.counted);
+    }
 
-    let could_be_seeking = old_status.current_song == status.current_song
-        && old_status.state == status.state
-        && status.state == PlayState::Playing;
-    Ok(could_be_seeking)
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_volume() {
+        let (status, _) = parse_status(pairs(&[("volume", "40")]));
+        assert_eq!(status.volume, Some(40));
+
+        // MPD without mixer
+        let (status, _) = parse_status(pairs(&[("volume", "-1")]));
+        assert_eq!(status.volume, None);
+
+        let (status, song_id) = parse_status(pairs(&[("volume", "120"), ("songid", "7")]));
+        assert_eq!(status.volume, Some(100));
+        assert_eq!(song_id, Some(7));
+    }
 }
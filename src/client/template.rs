@@ -0,0 +1,126 @@
+use super::placeholder::{placeholders, render_with};
+use super::{Song, Status};
+
+/// All placeholders that can be used in a template
+const PLACEHOLDERS: [&str; 10] = [
+    "file",
+    "filename",
+    "artist",
+    "albumartist",
+    "album",
+    "title",
+    "genre",
+    "date",
+    "track",
+    "disc",
+];
+
+/// Placeholders describing the player, which can only be used in a status template
+const STATUS_PLACEHOLDERS: [&str; 2] = ["state", "volume"];
+
+/// Checks that a template only contains known placeholders.
+/// Returns the first unknown placeholder as the error variant
+pub fn validate(template: &str) -> Result<(), String> {
+    validate_with(template, &[])
+}
+
+/// Checks that a status template only contains known placeholders, see [render_status].
+/// Returns the first unknown placeholder as the error variant
+pub fn validate_status(template: &str) -> Result<(), String> {
+    validate_with(template, &STATUS_PLACEHOLDERS)
+}
+
+/// Renders a template for the given song, replacing placeholders like `{title}` with the tags of the song.
+///
+/// Tags the song does not have are replaced with an empty string, except for the title which falls back
+/// to the file name. Multiple artists or genres are joined with a comma, unknown placeholders are kept as is
+pub fn render(template: &str, song: &Song) -> String {
+    render_with(template, |name| song_value(name, song))
+}
+
+/// Renders a template for the current song like [render], additionally replacing `{state}` with
+/// `playing`, `paused` or `stopped` and `{volume}` with the volume
+pub fn render_status(template: &str, status: &Status) -> String {
+    render_with(template, |name| match name {
+        "state" => Some(status.state.as_str().to_string()),
+        "volume" => Some(status.volume.map(|v| v.to_string()).unwrap_or_default()),
+        _ => match &status.current_song {
+            Some(song) => song_value(name, song),
+            None => PLACEHOLDERS.contains(&name).then(String::new),
+        },
+    })
+}
+
+fn validate_with(template: &str, extra: &[&str]) -> Result<(), String> {
+    match placeholders(template).find(|p| !PLACEHOLDERS.contains(p) && !extra.contains(p)) {
+        Some(p) => Err(p.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Returns the value of the placeholder with the given name for the song, or None if the placeholder is unknown
+fn song_value(name: &str, song: &Song) -> Option<String> {
+    let filename = || {
+        song.uri
+            .file_stem()
+            .map_or(String::new(), |s| s.to_string_lossy().into_owned())
+    };
+
+    let value = match name {
+        "file" => song.uri.to_string_lossy().into_owned(),
+        "filename" => filename(),
+        "artist" => song.artists.join(", "),
+        "albumartist" => song.album_artists.join(", "),
+        "album" => song.album.as_deref().unwrap_or_default().to_string(),
+        "title" => song.title.as_deref().map_or_else(filename, str::to_string),
+        "genre" => song.genres.join(", "),
+        "date" => song.date.map(|d| d.to_string()).unwrap_or_default(),
+        "track" => song.track.map(|t| t.to_string()).unwrap_or_default(),
+        "disc" => song.disc.map(|d| d.to_string()).unwrap_or_default(),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let result = render("{track}. {title} by {artist} on {album}", &Song::test_song());
+
+        assert_eq!(result, "7. Resurrections by Lena Raine, 2 Mello on Celeste");
+    }
+
+    #[test]
+    fn test_missing_tags_and_unknown_placeholders() {
+        let result = render("{date}|{genre}|{unknown}|{unclosed", &Song::test_song());
+
+        assert_eq!(result, "||{unknown}|{unclosed");
+
+        // the title falls back to the file name
+        let mut song = Song::test_song();
+        song.title = None;
+        assert_eq!(render("{title}", &song), "Resurrections");
+    }
+
+    #[test]
+    fn test_render_status() {
+        let mut status = Status::new();
+        status.volume = Some(40);
+        assert_eq!(render_status("{title} ({state}, {volume}%)", &status), " (stopped, 40%)");
+
+        status.current_song = Some(Song::test_song());
+        assert_eq!(render_status("{title}", &status), "Resurrections");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(validate("{title} - {artist}"), Ok(()));
+        assert_eq!(validate("{title} ({year})"), Err(String::from("year")));
+        assert_eq!(validate("{state}"), Err(String::from("state")));
+        assert_eq!(validate_status("{state}: {title}"), Ok(()));
+    }
+}
@@ -1,25 +1,25 @@
 use async_std::{fs, io, sync::RwLock};
 use log::{info, warn};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 
-use std::net::{IpAddr, Ipv4Addr};
-use std::{env, path::Path, path::PathBuf, sync::OnceLock};
+use std::fmt::{self, Display, Formatter};
+use std::{env, path::Path, path::PathBuf, str::FromStr, sync::OnceLock};
 
 use crate::HOME_DIR;
 use crate::args::Args;
-use crate::util::expand::serde_expand_path;
-use dns_lookup::lookup_host;
+use crate::client::{validate_cover_pattern, validate_template};
+use crate::util::expand::{expand_path, serde_expand_path};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Config {
     #[serde(default = "default_addr")]
-    /// The IP address of MPD to connect to
-    pub addr: IpAddr,
+    /// The host, IP address or unix socket of MPD to connect to
+    pub addr: Address,
     #[serde(default = "default_port")]
     /// The port of MPD to connect to
     pub port: u16,
     #[serde(default = "default_retries")]
-    /// Amount of time to retry to connect
+    /// Amount of times to retry to connect after the config was reloaded, negative values are treated as 0
     pub retries: isize,
     #[serde(default = "default_music_dir")]
     #[serde(deserialize_with = "serde_expand_path")]
@@ -29,6 +29,207 @@ pub struct Config {
     #[serde(deserialize_with = "serde_expand_path")]
     /// The dedicated root directory mpdris uses to search for covers
     pub cover_directory: PathBuf,
+    #[serde(default = "default_cover_cache_size")]
+    /// The maximum size of the covers stored in the cover cache in MiB
+    pub cover_cache_size: u64,
+    #[serde(default = "default_cover_patterns")]
+    /// Templates of the paths to search for covers, tried in order
+    pub cover_patterns: Vec<String>,
+    #[serde(default = "default_rating_sticker")]
+    /// The sticker storing the rating of a song, empty to disable ratings
+    pub rating_sticker: String,
+    #[serde(default = "default_rating_max")]
+    /// The value of the rating sticker representing the highest rating
+    pub rating_max: u32,
+    #[serde(default = "default_play_count_sticker")]
+    /// The sticker storing how often a song was played, empty to disable play counts
+    pub play_count_sticker: String,
+    #[serde(default)]
+    /// If mpdris increments the play count of a song once it was played past the threshold
+    pub count_plays: bool,
+    #[serde(default = "default_play_count_threshold")]
+    /// Percentage of a song that has to be played before its play count is incremented
+    pub play_count_threshold: u8,
+    #[serde(default)]
+    /// The format of the listening history, or None to not keep a listening history
+    pub history: Option<HistoryFormat>,
+    #[serde(default)]
+    /// If mpdris shows a desktop notification when the song changes
+    pub notifications: bool,
+    #[serde(default = "default_notification_summary")]
+    /// Template of the summary of the notification
+    pub notification_summary: String,
+    #[serde(default = "default_notification_body")]
+    /// Template of the body of the notification
+    pub notification_body: String,
+    #[serde(default = "default_notification_timeout")]
+    /// Time in milliseconds after which the notification is closed, -1 to let the notification server decide
+    pub notification_timeout: i32,
+    #[serde(default, skip_serializing)]
+    /// The password used to authenticate to MPD
+    pub password: Option<String>,
+    #[serde(default, deserialize_with = "serde_expand_path")]
+    /// A file containing the password used to authenticate to MPD, used if no password is set
+    pub password_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// The partitions of MPD to control, each exposed as its own player. Empty to control the default partition
+    pub partition: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// The players to expose, each connecting to its own MPD server or partition. Takes precedence over partition
+    pub player: Vec<PlayerConfig>,
+    #[serde(default)]
+    /// The commands run when the state of the player changes
+    pub hooks: HooksConfig,
+}
+
+/// Commands run by `sh -c` when the corresponding event occurs, see [crate::hooks]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HooksConfig {
+    #[serde(default)]
+    /// Run when the current song changes
+    pub song_changed: Option<String>,
+    #[serde(default)]
+    /// Run when playback is started, paused or stopped
+    pub play_state: Option<String>,
+    #[serde(default)]
+    /// Run when the volume changes
+    pub volume: Option<String>,
+    #[serde(default)]
+    /// Run when the connection to MPD is established
+    pub connected: Option<String>,
+    #[serde(default)]
+    /// Run when the connection to MPD is lost
+    pub disconnected: Option<String>,
+    #[serde(default = "default_hook_timeout")]
+    /// Time in seconds after which a hook gets killed
+    pub timeout: u64,
+}
+
+/// A player exposed as its own MPRIS player, values that are not set are taken from the [Config]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerConfig {
+    /// Name of the player, appended to the bus name
+    pub label: String,
+    #[serde(default)]
+    /// The name of the player shown to the user
+    pub identity: Option<String>,
+    #[serde(default)]
+    /// The host, IP address or unix socket of MPD to connect to
+    pub addr: Option<Address>,
+    #[serde(default)]
+    /// The port of MPD to connect to
+    pub port: Option<u16>,
+    #[serde(default, skip_serializing)]
+    /// The password used to authenticate to MPD
+    pub password: Option<String>,
+    #[serde(default, deserialize_with = "serde_expand_path")]
+    /// A file containing the password used to authenticate to MPD, used if no password is set
+    pub password_file: Option<PathBuf>,
+    #[serde(default)]
+    /// The partition of MPD to control
+    pub partition: Option<String>,
+}
+
+/// The format of the listening history
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryFormat {
+    /// One JSON object per song
+    Jsonl,
+    /// The `.scrobbler.log` format of the Audioscrobbler portable player logging specification
+    Scrobbler,
+}
+
+/// Everything needed to connect to an MPD server, see [Config::server]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    pub addr: Address,
+    pub port: u16,
+    pub password: Option<String>,
+    /// The partition to switch to after connecting, or None to stay in the default partition
+    pub partition: Option<String>,
+}
+
+impl PlayerConfig {
+    /// Creates a player controlling the given partition of the MPD server set in the [Config]
+    fn from_partition(partition: &str) -> Self {
+        Self {
+            label: partition.to_string(),
+            identity: None,
+            addr: None,
+            port: None,
+            password: None,
+            password_file: None,
+            partition: Some(partition.to_string()),
+        }
+    }
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            song_changed: None,
+            play_state: None,
+            volume: None,
+            connected: None,
+            disconnected: None,
+            timeout: DEFAULT_HOOK_TIMEOUT,
+        }
+    }
+}
+
+/// The address over which to connect to MPD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    /// A hostname or IP address, used together with [Config::port].
+    /// Hostnames are resolved every time a connection is established
+    Host(String),
+    /// The path to a unix domain socket
+    Socket(PathBuf),
+    /// The name of an abstract unix domain socket, without the leading `@`
+    Abstract(String),
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    /// Parses an address as described in the
+    /// [MPD client specifications](https://mpd.readthedocs.io/en/stable/client.html#connecting-to-mpd).
+    /// Strings starting with `@` are abstract sockets, strings starting with `/`, `~` or `$` are
+    /// socket paths (which get expanded) and everything else is a hostname or IP address
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = s.strip_prefix('@') {
+            Ok(Self::Abstract(name.to_string()))
+        } else if s.starts_with(['/', '~', '$']) {
+            Ok(Self::Socket(expand_path(s)))
+        } else if s.is_empty() || s.contains(char::is_whitespace) {
+            Err(format!("`{s}` is neither a valid host nor a socket path"))
+        } else {
+            Ok(Self::Host(s.to_string()))
+        }
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Host(host) => f.write_str(host),
+            Self::Socket(path) => path.display().fmt(f),
+            Self::Abstract(name) => write!(f, "@{name}"),
+        }
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        String::deserialize(de)?.parse().map_err(de::Error::custom)
+    }
 }
 
 impl Default for Config {
@@ -37,9 +238,25 @@ impl Default for Config {
     }
 }
 
-const DEFAULT_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+const DEFAULT_HOST: &str = "127.0.0.1";
 const DEFAULT_PORT: u16 = 6600;
 const DEFAULT_RETRIES: isize = 3;
+const DEFAULT_COVER_CACHE_SIZE: u64 = 50;
+const DEFAULT_RATING_STICKER: &str = "rating";
+const DEFAULT_RATING_MAX: u32 = 10;
+const DEFAULT_PLAY_COUNT_STICKER: &str = "playcount";
+const DEFAULT_PLAY_COUNT_THRESHOLD: u8 = 50;
+const DEFAULT_NOTIFICATION_SUMMARY: &str = "{title}";
+const DEFAULT_NOTIFICATION_BODY: &str = "{artist} - {album}";
+const DEFAULT_NOTIFICATION_TIMEOUT: i32 = -1;
+const DEFAULT_HOOK_TIMEOUT: u64 = 10;
+/// The cover patterns searched by default, in the order earlier versions searched for covers
+pub const DEFAULT_COVER_PATTERNS: [&str; 4] = [
+    "{cover_directory}/{directory}/{filename}",
+    "{cover_directory}/{directory}",
+    "{music_directory}/{directory}/{filename}",
+    "{music_directory}/{directory}/cover",
+];
 
 pub static CONFIG: OnceLock<RwLock<Config>> = OnceLock::new();
 
@@ -53,11 +270,56 @@ pub fn config() -> &'static RwLock<Config> {
 impl Config {
     pub fn new() -> Self {
         Self {
-            addr: DEFAULT_ADDR,
+            addr: default_addr(),
             port: DEFAULT_PORT,
             retries: DEFAULT_RETRIES,
             music_directory: default_music_dir(),
             cover_directory: default_cover_dir(),
+            cover_cache_size: DEFAULT_COVER_CACHE_SIZE,
+            cover_patterns: default_cover_patterns(),
+            rating_sticker: default_rating_sticker(),
+            rating_max: DEFAULT_RATING_MAX,
+            play_count_sticker: default_play_count_sticker(),
+            count_plays: false,
+            play_count_threshold: DEFAULT_PLAY_COUNT_THRESHOLD,
+            history: None,
+            notifications: false,
+            notification_summary: default_notification_summary(),
+            notification_body: default_notification_body(),
+            notification_timeout: DEFAULT_NOTIFICATION_TIMEOUT,
+            password: None,
+            password_file: None,
+            partition: Vec::new(),
+            player: Vec::new(),
+            hooks: HooksConfig::default(),
+        }
+    }
+
+    /// Returns the players to expose, either the configured players or one player per configured partition.
+    /// Empty if only the default partition of the MPD server set in the config is controlled
+    pub fn players(&self) -> Vec<PlayerConfig> {
+        if self.player.is_empty() {
+            self.partition.iter().map(|p| PlayerConfig::from_partition(p)).collect()
+        } else {
+            self.player.clone()
+        }
+    }
+
+    /// Returns the server the player with the given label connects to.
+    /// Falls back to the server set in the config if there is no player with that label
+    pub fn server(&self, label: Option<&str>) -> Server {
+        let player = label.and_then(|label| self.players().into_iter().find(|p| p.label == label));
+        let player = player.as_ref();
+
+        Server {
+            addr: player.and_then(|p| p.addr.clone()).unwrap_or_else(|| self.addr.clone()),
+            port: player.and_then(|p| p.port).unwrap_or(self.port),
+            password: match player {
+                // a player on another server does not share the password of the default server
+                Some(p) if p.addr.is_some() || p.port.is_some() => p.password.clone(),
+                _ => player.and_then(|p| p.password.clone()).or_else(|| self.password.clone()),
+            },
+            partition: player.and_then(|p| p.partition.clone()),
         }
     }
 
@@ -100,11 +362,13 @@ impl Config {
     ///   it will take its values instead of the ones specified in the config as per
     ///   the [MPD client specifications](https://mpd.readthedocs.io/en/stable/client.html#connecting-to-mpd)
     /// - If an argument is specified it will use the value from the argument
+    /// - If no password is set, but a password file is, the password will be read from that file
     ///
     /// ## Errors
-    /// - PermissionDenied if the process lacks the permissions to read the file
+    /// - PermissionDenied if the process lacks the permissions to read the file or password file
     /// - InvalidData if the file read contains invalid UTF-8
     /// - InvalidData if the file cannot be deserialized into a config
+    /// - InvalidData if a player has an empty label or multiple players have the same label
     /// - Some other I/O error further specified in [fs::read_to_string]
     pub async fn load_config(file: &Path, args: &Args) -> io::Result<Self> {
         let mut config = if file.exists() {
@@ -118,6 +382,49 @@ impl Config {
 
         config.load_from_args(args);
 
+        if config.rating_max == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "rating_max must be greater than 0",
+            ));
+        }
+        if config.play_count_threshold > 100 {
+            warn!(
+                "play_count_threshold is a percentage, songs will never reach {}%",
+                config.play_count_threshold
+            );
+        }
+
+        for pattern in &config.cover_patterns {
+            if let Err(placeholder) = validate_cover_pattern(pattern) {
+                warn!("Cover pattern `{pattern}` contains unknown placeholder `{{{placeholder}}}` and will never match");
+            }
+        }
+
+        for template in [&config.notification_summary, &config.notification_body] {
+            if let Err(placeholder) = validate_template(template) {
+                warn!("Notification template `{template}` contains unknown placeholder `{{{placeholder}}}`");
+            }
+        }
+
+        if !config.player.is_empty() && !config.partition.is_empty() {
+            warn!("Both players and partitions are configured, ignoring the partitions");
+        }
+        validate_players(&config.players())?;
+
+        if config.password.is_none()
+            && let Some(file) = &config.password_file
+        {
+            config.password = Some(read_password_file(file).await?);
+        }
+        for player in &mut config.player {
+            if player.password.is_none()
+                && let Some(file) = &player.password_file
+            {
+                player.password = Some(read_password_file(file).await?);
+            }
+        }
+
         Ok(config)
     }
 
@@ -125,26 +432,31 @@ impl Config {
         if let Some(port) = args.port {
             self.port = port;
         }
-        if let Some(addr) = args.addr {
-            self.addr = addr;
+        if let Some(addr) = &args.addr {
+            self.addr = addr.clone();
         }
         if let Some(retries) = args.retries {
             self.retries = retries;
         }
+        if !args.partition.is_empty() {
+            self.partition = args.partition.clone();
+        }
     }
 
     /// Loads values $MPD_HOST and $MPD_PORT from environment
     fn load_from_env_vars(&mut self) -> io::Result<()> {
-        if let Ok(addr) = env::var("MPD_HOST") {
-            self.addr = lookup_host(addr.as_str())
-                .map_err(|_e| {
-                    io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Could not resolve the $MPD_HOST environment variable into an IP address.",
-                    )
-                })?
-                .next()
-                .ok_or(io::Error::new(io::ErrorKind::InvalidData, "Could not resolve $MPD_HOST"))?;
+        if let Ok(value) = env::var("MPD_HOST") {
+            let (password, addr) = split_mpd_host(&value);
+            if let Some(password) = password {
+                self.password = Some(password.to_string());
+            }
+
+            // as per the client specifications, only absolute paths & abstract sockets are allowed
+            self.addr = if addr.starts_with(['/', '@']) {
+                addr.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            } else {
+                Address::Host(addr.to_string())
+            };
         }
 
         if let Ok(port) = env::var("MPD_PORT") {
@@ -159,22 +471,222 @@ impl Config {
         Ok(())
     }
 
-    /// Loads config from file
+    /// Loads config from file, warning about unknown keys
     async fn load_from_file(file: &Path) -> io::Result<Self> {
         let data = fs::read_to_string(file).await?;
 
-        toml::from_str::<Config>(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.message()))
+        let mut unknown = Vec::new();
+        let config = toml::Deserializer::parse(&data)
+            .and_then(|de| serde_ignored::deserialize(de, |path| unknown.push(path.to_string())))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, parse_error(file, &data, &e)))?;
+
+        for key in unknown {
+            warn!("Unknown key `{key}` in `{}` is ignored", file.display());
+        }
+
+        Ok(config)
+    }
+
+    /// Checks that the configured paths exist, warning about paths that are optional.
+    ///
+    /// ## Errors
+    /// - NotFound if the music directory does not exist
+    pub fn check_paths(&self) -> io::Result<()> {
+        if !self.cover_directory.is_dir() {
+            warn!("The cover directory `{}` does not exist", self.cover_directory.display());
+        }
+        if let Address::Socket(socket) = &self.addr
+            && !socket.exists()
+        {
+            warn!("The socket `{}` does not exist, is MPD running?", socket.display());
+        }
+
+        if !self.music_directory.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("The music directory `{}` does not exist", self.music_directory.display()),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Serializes the config, noting above each value whether it was set by an argument, an environment
+    /// variable, the config file or is the default value. Passwords are left out
+    pub async fn to_annotated_string(&self, file: &Path, args: &Args) -> io::Result<String> {
+        let file_keys: Vec<String> = match fs::read_to_string(file).await {
+            Ok(data) => toml::from_str::<toml::Table>(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, parse_error(file, &data, &e)))?
+                .keys()
+                .cloned()
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+
+        let source = |key: &str| {
+            let arg = match key {
+                "addr" => args.addr.is_some(),
+                "port" => args.port.is_some(),
+                "retries" => args.retries.is_some(),
+                "partition" => !args.partition.is_empty(),
+                _ => false,
+            };
+            let var = match key {
+                "addr" => "MPD_HOST",
+                "port" => "MPD_PORT",
+                _ => "",
+            };
+
+            if arg {
+                format!("set by the argument --{key}")
+            } else if !var.is_empty() && env::var_os(var).is_some() {
+                format!("set by ${var}")
+            } else if file_keys.iter().any(|k| k == key) {
+                format!("set in {}", file.display())
+            } else {
+                String::from("default")
+            }
+        };
+
+        let data = toml::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let mut ret = String::from("# The configuration used by mpdris, passwords are not shown\n\n");
+        let mut annotated = Vec::new();
+        let mut in_table = false;
+
+        for line in data.lines() {
+            in_table |= line.starts_with('[');
+            // top level keys and tables, but not the keys inside of tables or multi-line arrays
+            let key = match line.trim_start_matches('[').split_once([' ', ']']) {
+                Some((key, _)) if line.starts_with('[') => key,
+                Some((key, _)) if !in_table && !line.starts_with([' ', ']']) => key,
+                _ => "",
+            };
+            if !key.is_empty() && !annotated.contains(&key) {
+                ret.push_str(&format!("# {}\n", source(key)));
+                annotated.push(key);
+            }
+
+            ret.push_str(line);
+            ret.push('\n');
+        }
+
+        Ok(ret)
     }
 }
 
+/// Splits the value of `$MPD_HOST` into the password and the host, as it may have the form `password@host`.
+/// An `@` at the start denotes an abstract socket instead and socket paths may contain an `@` as well
+fn split_mpd_host(value: &str) -> (Option<&str>, &str) {
+    match value.split_once('@') {
+        Some((password, host)) if !password.is_empty() && !password.starts_with('/') && !host.is_empty() => {
+            (Some(password), host)
+        }
+        _ => (None, value),
+    }
+}
+
+/// Converts the label of a player into the name used in its bus name and the name of its files,
+/// replacing all characters that are not valid in an element of a bus name with `_`
+pub fn label_name(label: &str) -> String {
+    let name: String = label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+
+    // elements must not be empty or start with a digit
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+/// Checks that every player has a label and that no two labels result in the same bus name
+fn validate_players(players: &[PlayerConfig]) -> io::Result<()> {
+    for (i, player) in players.iter().enumerate() {
+        if player.label.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The label of a player must not be empty",
+            ));
+        }
+
+        let element = label_name(&player.label);
+        if let Some(other) = players[..i].iter().find(|p| label_name(&p.label) == element) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The players labeled `{}` and `{}` would both use the bus name element `{element}`",
+                    other.label, player.label
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Formats an error of the toml parser as `<file>:<line>:<column>: <message>`
+fn parse_error(file: &Path, data: &str, err: &toml::de::Error) -> String {
+    let Some(span) = err.span() else {
+        return format!("{}: {}", file.display(), err.message());
+    };
+
+    let before = &data[..span.start.min(data.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+
+    format!("{}:{line}:{column}: {}", file.display(), err.message())
+}
+
+/// Reads a password from the given file, ignoring trailing newlines
+async fn read_password_file(file: &Path) -> io::Result<String> {
+    let password = fs::read_to_string(file)
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("Could not read password file `{}`: {e}", file.display())))?;
+
+    Ok(password.trim_end_matches(['\n', '\r']).to_string())
+}
+
 fn default_music_dir() -> PathBuf {
     HOME_DIR.join("Music")
 }
 fn default_cover_dir() -> PathBuf {
     HOME_DIR.join("Music/covers")
 }
-fn default_addr() -> IpAddr {
-    DEFAULT_ADDR
+fn default_cover_cache_size() -> u64 {
+    DEFAULT_COVER_CACHE_SIZE
+}
+fn default_cover_patterns() -> Vec<String> {
+    DEFAULT_COVER_PATTERNS.map(String::from).to_vec()
+}
+fn default_rating_sticker() -> String {
+    DEFAULT_RATING_STICKER.to_string()
+}
+fn default_rating_max() -> u32 {
+    DEFAULT_RATING_MAX
+}
+fn default_play_count_sticker() -> String {
+    DEFAULT_PLAY_COUNT_STICKER.to_string()
+}
+fn default_play_count_threshold() -> u8 {
+    DEFAULT_PLAY_COUNT_THRESHOLD
+}
+fn default_notification_summary() -> String {
+    DEFAULT_NOTIFICATION_SUMMARY.to_string()
+}
+fn default_notification_body() -> String {
+    DEFAULT_NOTIFICATION_BODY.to_string()
+}
+fn default_notification_timeout() -> i32 {
+    DEFAULT_NOTIFICATION_TIMEOUT
+}
+fn default_hook_timeout() -> u64 {
+    DEFAULT_HOOK_TIMEOUT
+}
+fn default_addr() -> Address {
+    Address::Host(DEFAULT_HOST.to_string())
 }
 fn default_port() -> u16 {
     DEFAULT_PORT
@@ -182,3 +694,115 @@ fn default_port() -> u16 {
 fn default_retries() -> isize {
     DEFAULT_RETRIES
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_mpd_host() {
+        assert_eq!(split_mpd_host("localhost"), (None, "localhost"));
+        assert_eq!(split_mpd_host("secret@localhost"), (Some("secret"), "localhost"));
+        assert_eq!(split_mpd_host("secret@/run/mpd/socket"), (Some("secret"), "/run/mpd/socket"));
+        assert_eq!(split_mpd_host("secret@@mpd"), (Some("secret"), "@mpd"));
+        assert_eq!(split_mpd_host("@mpd"), (None, "@mpd"));
+        assert_eq!(
+            split_mpd_host("/run/user@1000/mpd/socket"),
+            (None, "/run/user@1000/mpd/socket")
+        );
+        assert_eq!(split_mpd_host("host@"), (None, "host@"));
+    }
+
+    #[test]
+    fn test_label_name() {
+        assert_eq!(label_name("kitchen"), "kitchen");
+        assert_eq!(label_name("living-room"), "living-room");
+        assert_eq!(label_name("living room.2"), "living_room_2");
+        assert_eq!(label_name("2nd"), "_2nd");
+        assert_eq!(label_name("küche"), "k_che");
+        assert_eq!(label_name(""), "_");
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!("127.0.0.1".parse(), Ok(default_addr()));
+        assert_eq!("::1".parse(), Ok(Address::Host(String::from("::1"))));
+        assert_eq!("musicbox.lan".parse(), Ok(Address::Host(String::from("musicbox.lan"))));
+        assert_eq!(
+            "/run/mpd/socket".parse(),
+            Ok(Address::Socket(PathBuf::from("/run/mpd/socket")))
+        );
+        assert_eq!("@mpd".parse(), Ok(Address::Abstract(String::from("mpd"))));
+        assert!("not an address".parse::<Address>().is_err());
+        assert!("".parse::<Address>().is_err());
+    }
+
+    /// Creates a config with the default connection settings without touching the home directory,
+    /// which must only be resolved after the tests of [expand_path] changed $HOME
+    fn test_config() -> Config {
+        toml::from_str("music_directory = \"\"\ncover_directory = \"\"").unwrap()
+    }
+
+    #[test]
+    fn test_players_from_partitions() {
+        let mut config = test_config();
+        assert!(config.players().is_empty());
+
+        config.partition = vec![String::from("kitchen")];
+        assert_eq!(config.players(), vec![PlayerConfig::from_partition("kitchen")]);
+
+        // configured players take precedence over partitions
+        config.player = vec![PlayerConfig::from_partition("living-room")];
+        assert_eq!(config.players(), vec![PlayerConfig::from_partition("living-room")]);
+    }
+
+    #[test]
+    fn test_server() {
+        let mut config = test_config();
+        config.password = Some(String::from("secret"));
+        config.player = vec![
+            PlayerConfig::from_partition("kitchen"),
+            PlayerConfig {
+                addr: Some(Address::Host(String::from("musicbox.lan"))),
+                ..PlayerConfig::from_partition("musicbox")
+            },
+        ];
+        config.player[1].partition = None;
+
+        let kitchen = config.server(Some("kitchen"));
+        assert_eq!(kitchen.addr, default_addr());
+        assert_eq!(kitchen.password.as_deref(), Some("secret"));
+        assert_eq!(kitchen.partition.as_deref(), Some("kitchen"));
+
+        // another server does not get the password of the default server
+        let musicbox = config.server(Some("musicbox"));
+        assert_eq!(musicbox.addr, Address::Host(String::from("musicbox.lan")));
+        assert_eq!(musicbox.port, DEFAULT_PORT);
+        assert_eq!(musicbox.password, None);
+        assert_eq!(musicbox.partition, None);
+
+        assert_eq!(config.server(None).partition, None);
+        assert_eq!(config.server(Some("unknown")), config.server(None));
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let data = "music_directory = \"\"\nport = \"abc\"\n";
+        let err = toml::from_str::<Config>(data).unwrap_err();
+
+        assert_eq!(
+            parse_error(Path::new("mpdris.conf"), data, &err),
+            "mpdris.conf:2:8: invalid type: string \"abc\", expected u16"
+        );
+    }
+
+    #[test]
+    fn test_validate_players() {
+        let player = |label: &str| PlayerConfig::from_partition(label);
+
+        assert!(validate_players(&[player("living-room"), player("kitchen")]).is_ok());
+        assert!(validate_players(&[player("")]).is_err());
+        assert!(validate_players(&[player("kitchen"), player("kitchen")]).is_err());
+        assert!(validate_players(&[player("living room"), player("living_room")]).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::{collections::HashMap, str::FromStr, time::Duration};
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use zbus::{Connection, proxy};
+
+use crate::args::{CtlArgs, CtlCommand, StatusCommand};
+use crate::client::{PlayState, Song, Status, render_status_template};
+
+/// Prefix of the bus names of all MPRIS players
+const BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+#[proxy(interface = "org.mpris.MediaPlayer2.Player", default_path = "/org/mpris/MediaPlayer2")]
+trait Player {
+    fn play(&self) -> zbus::Result<()>;
+    fn pause(&self) -> zbus::Result<()>;
+    fn play_pause(&self) -> zbus::Result<()>;
+    fn stop(&self) -> zbus::Result<()>;
+    fn next(&self) -> zbus::Result<()>;
+    fn previous(&self) -> zbus::Result<()>;
+    fn seek(&self, offset: i64) -> zbus::Result<()>;
+    fn set_position(&self, track_id: &ObjectPath<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+    #[zbus(property)]
+    fn volume(&self) -> zbus::Result<f64>;
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn position(&self) -> zbus::Result<i64>;
+}
+
+/// A position to seek to, parsed from e.g. `90`, `1:30`, `+5s` or `-10`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekTarget {
+    Absolute(Duration),
+    Forward(Duration),
+    Backward(Duration),
+}
+
+impl FromStr for SeekTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sign, time) = split_sign(s);
+        let time = time.strip_suffix('s').unwrap_or(time);
+
+        let secs = match time.split_once(':') {
+            Some((mins, secs)) => mins
+                .parse::<u64>()
+                .ok()
+                .zip(secs.parse::<f64>().ok())
+                .map(|(m, s)| m as f64 * 60.0 + s),
+            None => time.parse::<f64>().ok(),
+        };
+        let time = secs
+            .and_then(|s| Duration::try_from_secs_f64(s).ok())
+            .ok_or_else(|| format!("Invalid position `{s}`, expected e.g. 90, 1:30, +5s or -10s"))?;
+
+        Ok(match sign {
+            Some(true) => Self::Forward(time),
+            Some(false) => Self::Backward(time),
+            None => Self::Absolute(time),
+        })
+    }
+}
+
+/// A change of the volume in percent, parsed from e.g. `50`, `50%`, `+5%` or `-10`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VolumeChange {
+    Set(f64),
+    Change(f64),
+}
+
+impl FromStr for VolumeChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sign, volume) = split_sign(s);
+        let volume = volume.strip_suffix('%').unwrap_or(volume);
+
+        let volume = volume
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .ok_or_else(|| format!("Invalid volume `{s}`, expected e.g. 50%, +5% or -10%"))?;
+
+        Ok(match sign {
+            Some(true) => Self::Change(volume),
+            Some(false) => Self::Change(-volume),
+            None => Self::Set(volume),
+        })
+    }
+}
+
+/// Splits a leading + or - off s, returning true for + and false for -
+fn split_sign(s: &str) -> (Option<bool>, &str) {
+    if let Some(rest) = s.strip_prefix('+') {
+        (Some(true), rest)
+    } else if let Some(rest) = s.strip_prefix('-') {
+        (Some(false), rest)
+    } else {
+        (None, s)
+    }
+}
+
+/// Sends the command to the player over the session bus
+pub async fn run(args: &CtlArgs) -> zbus::Result<()> {
+    let name = match args.player.strip_prefix(BUS_NAME_PREFIX) {
+        Some(_) => args.player.clone(),
+        None => format!("{BUS_NAME_PREFIX}{}", args.player),
+    };
+
+    let connection = Connection::session().await?;
+    let player = PlayerProxy::builder(&connection)
+        .destination(name)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+
+    match &args.command {
+        CtlCommand::Play(_) => player.play().await,
+        CtlCommand::Pause(_) => player.pause().await,
+        CtlCommand::Toggle(_) => player.play_pause().await,
+        CtlCommand::Stop(_) => player.stop().await,
+        CtlCommand::Next(_) => player.next().await,
+        CtlCommand::Previous(_) => player.previous().await,
+        CtlCommand::Seek(cmd) => seek(&player, cmd.target).await,
+        CtlCommand::Volume(cmd) => {
+            let volume = match cmd.volume {
+                VolumeChange::Set(volume) => volume / 100.0,
+                VolumeChange::Change(change) => player.volume().await? + change / 100.0,
+            };
+            player.set_volume(volume.clamp(0.0, 1.0)).await
+        }
+        CtlCommand::Status(cmd) => print_status(&player, cmd).await,
+    }
+}
+
+async fn seek(player: &PlayerProxy<'_>, target: SeekTarget) -> zbus::Result<()> {
+    match target {
+        SeekTarget::Forward(offset) => player.seek(offset.as_micros() as i64).await,
+        SeekTarget::Backward(offset) => player.seek(-(offset.as_micros() as i64)).await,
+        SeekTarget::Absolute(position) => {
+            let metadata = player.metadata().await?;
+            let Some(Value::ObjectPath(track_id)) = metadata.get("mpris:trackid").map(|v| &**v) else {
+                return Err(zbus::Error::Failure(String::from("The player has no current song")));
+            };
+            player.set_position(track_id, position.as_micros() as i64).await
+        }
+    }
+}
+
+async fn print_status(player: &PlayerProxy<'_>, cmd: &StatusCommand) -> zbus::Result<()> {
+    let metadata = player.metadata().await?;
+
+    let mut status = Status::new();
+    status.state = match player.playback_status().await?.as_str() {
+        "Playing" => PlayState::Playing,
+        "Paused" => PlayState::Paused,
+        _ => PlayState::Stopped,
+    };
+    // not every player supports these
+    status.volume = player.volume().await.ok().map(|v| (v.clamp(0.0, 1.0) * 100.0).round() as u8);
+    let position = player.position().await.ok().map(|p| Duration::from_micros(p.max(0) as u64));
+    status.current_song = song_from_metadata(&metadata);
+
+    if cmd.json {
+        let song = status.current_song.as_ref();
+        let json = serde_json::json!({
+            "state": status.state.as_str(),
+            "title": song.and_then(|s| s.title.as_deref()),
+            "artist": song.map(|s| s.artists.iter().map(AsRef::as_ref).collect::<Vec<&str>>()),
+            "album": song.and_then(|s| s.album.as_deref()),
+            "position": position.map(|p| p.as_secs_f64()),
+            "duration": song.and_then(|s| s.duration).map(|d| d.as_secs_f64()),
+            "volume": status.volume,
+        });
+        println!("{json}");
+    } else if status.current_song.is_some() {
+        println!("{}", render_status_template(&cmd.template, &status));
+    } else {
+        println!("[{}]", status.state.as_str());
+    }
+
+    Ok(())
+}
+
+/// Creates a song from MPRIS metadata, or returns None if there is no current song
+fn song_from_metadata(metadata: &HashMap<String, OwnedValue>) -> Option<Song> {
+    let get = |key: &str| metadata.get(key).map(|v| &**v);
+    let string = |key: &str| match get(key) {
+        Some(Value::Str(s)) => Some(s.as_str().into()),
+        _ => None,
+    };
+    let strings = |key: &str| match get(key) {
+        Some(Value::Array(a)) => a
+            .iter()
+            .filter_map(|v| match v {
+                Value::Str(s) => Some(s.as_str().into()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    // players use different integer types, e.g. mpdris sends the track number as a byte
+    let integer = |key: &str| match get(key)? {
+        Value::U8(n) => Some(i64::from(*n)),
+        Value::I32(n) => Some(i64::from(*n)),
+        Value::U32(n) => Some(i64::from(*n)),
+        Value::I64(n) => Some(*n),
+        Value::U64(n) => i64::try_from(*n).ok(),
+        _ => None,
+    };
+
+    match get("mpris:trackid") {
+        Some(Value::ObjectPath(path)) if path.as_str() != "/org/mpris/MediaPlayer2/TrackList/NoTrack" => {}
+        _ => return None,
+    }
+
+    let mut song = Song::new();
+    if let Some(Value::Str(url)) = get("xesam:url") {
+        song.uri = url.strip_prefix("file://").unwrap_or(url).into();
+    }
+    song.title = string("xesam:title");
+    song.album = string("xesam:album");
+    song.cover = string("mpris:artUrl");
+    song.artists = strings("xesam:artist");
+    song.album_artists = strings("xesam:albumArtist");
+    song.genres = strings("xesam:genre");
+    song.track = integer("xesam:trackNumber").and_then(|n| u8::try_from(n).ok());
+    song.disc = integer("xesam:discNumber").and_then(|n| u8::try_from(n).ok());
+    song.duration = integer("mpris:length").map(|l| Duration::from_micros(l.max(0) as u64));
+
+    Some(song)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_seek_target() {
+        let secs = Duration::from_secs;
+
+        assert_eq!("90".parse(), Ok(SeekTarget::Absolute(secs(90))));
+        assert_eq!("1:30".parse(), Ok(SeekTarget::Absolute(secs(90))));
+        assert_eq!("+5s".parse(), Ok(SeekTarget::Forward(secs(5))));
+        assert_eq!("-10".parse(), Ok(SeekTarget::Backward(secs(10))));
+        assert!("+".parse::<SeekTarget>().is_err());
+        assert!("--5".parse::<SeekTarget>().is_err());
+        assert!("5m".parse::<SeekTarget>().is_err());
+    }
+
+    #[test]
+    fn test_parse_volume_change() {
+        assert_eq!("50".parse(), Ok(VolumeChange::Set(50.0)));
+        assert_eq!("50%".parse(), Ok(VolumeChange::Set(50.0)));
+        assert_eq!("+5%".parse(), Ok(VolumeChange::Change(5.0)));
+        assert_eq!("-10%".parse(), Ok(VolumeChange::Change(-10.0)));
+        assert!("loud".parse::<VolumeChange>().is_err());
+        assert!("-inf".parse::<VolumeChange>().is_err());
+    }
+
+    #[test]
+    fn test_song_from_metadata() {
+        let expected = Song::test_song();
+        let artists: Vec<&str> = expected.artists.iter().map(AsRef::as_ref).collect();
+
+        let mut metadata = HashMap::new();
+        let value = |v: Value<'_>| OwnedValue::try_from(v).unwrap();
+        let track_id = ObjectPath::try_from("/org/musicpd/mpris/1").unwrap();
+        metadata.insert(String::from("mpris:trackid"), value(track_id.into()));
+        metadata.insert(String::from("xesam:title"), value(expected.title.as_deref().unwrap().into()));
+        metadata.insert(String::from("xesam:artist"), value(artists.into()));
+        metadata.insert(String::from("xesam:trackNumber"), value(expected.track.unwrap().into()));
+
+        let song = song_from_metadata(&metadata).unwrap();
+        assert_eq!(song.title, expected.title);
+        assert_eq!(song.artists, expected.artists);
+        assert_eq!(song.track, expected.track);
+    }
+
+    #[test]
+    fn test_no_track() {
+        let mut metadata = HashMap::new();
+        let no_track = ObjectPath::try_from("/org/mpris/MediaPlayer2/TrackList/NoTrack").unwrap();
+        metadata.insert(
+            String::from("mpris:trackid"),
+            OwnedValue::try_from(Value::from(no_track)).unwrap(),
+        );
+
+        assert!(song_from_metadata(&metadata).is_none());
+    }
+}
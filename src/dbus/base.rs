@@ -1,8 +1,10 @@
-pub struct BaseInterface;
+pub struct BaseInterface {
+    identity: String,
+}
 
 impl BaseInterface {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(identity: String) -> Self {
+        Self { identity }
     }
 }
 
@@ -38,25 +40,24 @@ impl BaseInterface {
 
     #[zbus(property, name = "HasTrackList")]
     async fn has_tracklist(&self) -> bool {
-        false // todo implement tracklist interface
+        true
     }
 
     #[zbus(property)]
     async fn identity(&self) -> &str {
-        "Music Player Daemon"
+        &self.identity
     }
 
     // todo add desktop entry
 
     #[zbus(property)]
     async fn supported_uri_schemes(&self) -> &[&str] {
-        // &["file"] todo add tracklist interface
-        &[]
+        &["file"]
     }
 
     #[zbus(property)]
     async fn supported_mime_types(&self) -> &[&str] {
-        // todo add tracklist interface
+        // todo query the decoders supported by MPD
         &[]
     }
 }
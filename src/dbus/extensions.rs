@@ -0,0 +1,191 @@
+use async_std::sync::RwLock;
+use log::error;
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+use zbus::fdo::{self, Properties};
+use zbus::interface;
+use zbus::object_server::{Interface, SignalEmitter};
+use zbus::zvariant::{ObjectPath, Value};
+
+use crate::client::{Command, MPDClient, Repeat, ReplayGainMode, Status, TriState};
+use crate::config::config;
+
+use super::path_to_id;
+use super::player::{PlayerInterface, loop_status};
+
+/// Playback options of MPD that cannot be expressed using MPRIS
+pub struct ExtensionsInterface {
+    mpd: Arc<MPDClient>,
+    status: Arc<RwLock<Status>>,
+}
+
+impl ExtensionsInterface {
+    pub async fn new(connection: Arc<MPDClient>) -> Self {
+        let status = connection.get_status();
+        Self { mpd: connection, status }
+    }
+}
+
+#[interface(name = "org.mpdris.Extensions")]
+impl ExtensionsInterface {
+    /// Sets the rating of the track with the given id between 0.0 and 1.0, values outside of that range get clamped
+    async fn set_rating(&self, track_id: ObjectPath<'_>, rating: f64) -> fdo::Result<()> {
+        let Some(id) = path_to_id(&track_id) else {
+            return Err(fdo::Error::InvalidArgs(format!("`{track_id}` is not a valid track id")));
+        };
+        if rating.is_nan() {
+            return Err(fdo::Error::InvalidArgs(String::from("Rating must be a number")));
+        }
+
+        let (sticker, max) = {
+            let c = config().read().await;
+            (c.rating_sticker.clone(), c.rating_max)
+        };
+        if sticker.is_empty() {
+            return Err(fdo::Error::NotSupported(String::from("Ratings are disabled in the config")));
+        }
+
+        let value = (rating.clamp(0.0, 1.0) * f64::from(max)).round() as u32;
+        self.mpd.set_sticker(id, &sticker, value).await.map_err(|e| {
+            error!("Failed to set rating: {e}");
+            e.into()
+        })
+    }
+
+    /// Whether the volume can be changed, false if MPD has no mixer
+    #[zbus(property)]
+    async fn can_set_volume(&self) -> bool {
+        self.status.read().await.volume.is_some()
+    }
+
+    /// Whether songs are removed from the queue after being played, one of `off`, `on` or `oneshot`
+    #[zbus(property)]
+    async fn consume(&self) -> &str {
+        tri_state_to_str(self.status.read().await.consume)
+    }
+
+    #[zbus(property)]
+    async fn set_consume(&mut self, consume: String) -> fdo::Result<()> {
+        let consume = tri_state_from_str(&consume)?;
+
+        self.mpd.request_data(&Command::consume(consume)).await.map_err(|e| {
+            error!("Failed to set consume: {e}");
+            e
+        })?;
+
+        self.status.write().await.consume = consume;
+        Ok(())
+    }
+
+    /// Whether playback stops after the current song, one of `off`, `on` or `oneshot`
+    #[zbus(property)]
+    async fn single(&self) -> &str {
+        tri_state_to_str(self.status.read().await.single)
+    }
+
+    #[zbus(property)]
+    async fn set_single(&mut self, single: String, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>) -> fdo::Result<()> {
+        let single = tri_state_from_str(&single)?;
+
+        self.mpd.request_data(&Command::single(single)).await.map_err(|e| {
+            error!("Failed to set single: {e}");
+            e
+        })?;
+
+        // the loop status of the player depends on single as well, see PlayerInterface::set_loop_status
+        let (old, new) = {
+            let mut s = self.status.write().await;
+            let old = s.repeat;
+            s.single = single;
+            s.repeat = match (single, old) {
+                (TriState::On, _) => Repeat::Single,
+                // the loop status Track enables repeat as well
+                (_, Repeat::Single) => Repeat::On,
+                (_, repeat) => repeat,
+            };
+            (old, s.repeat)
+        };
+
+        // the next change in MPD does not differ from the cached status anymore, so it is signaled here
+        if old != new {
+            let changed = HashMap::from([("LoopStatus", Value::from(loop_status(new)))]);
+            Properties::properties_changed(&emitter, PlayerInterface::name(), changed, Cow::Borrowed(&[])).await?;
+        }
+        Ok(())
+    }
+
+    /// The crossfade between songs in seconds, 0 if disabled
+    #[zbus(property)]
+    async fn crossfade(&self) -> u32 {
+        self.status.read().await.crossfade
+    }
+
+    #[zbus(property)]
+    async fn set_crossfade(&mut self, crossfade: u32) -> fdo::Result<()> {
+        self.mpd.request_data(&Command::crossfade(crossfade)).await.map_err(|e| {
+            error!("Failed to set crossfade: {e}");
+            e
+        })?;
+
+        self.status.write().await.crossfade = crossfade;
+        Ok(())
+    }
+
+    /// The replay gain mode, one of `off`, `track`, `album` or `auto`
+    #[zbus(property)]
+    async fn replay_gain_mode(&self) -> &str {
+        self.status.read().await.replay_gain_mode.as_str()
+    }
+
+    #[zbus(property)]
+    async fn set_replay_gain_mode(&mut self, mode: String) -> fdo::Result<()> {
+        let mode = ReplayGainMode::parse(&mode)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("`{mode}` is not a valid replay gain mode")))?;
+
+        self.mpd.request_data(&Command::replay_gain_mode(mode)).await.map_err(|e| {
+            error!("Failed to set replay gain mode: {e}");
+            e
+        })?;
+
+        self.status.write().await.replay_gain_mode = mode;
+        Ok(())
+    }
+
+    /// The volume threshold in decibels used by MixRamp
+    #[zbus(property)]
+    async fn mix_ramp_db(&self) -> f64 {
+        self.status.read().await.mixramp_db
+    }
+
+    #[zbus(property)]
+    async fn set_mix_ramp_db(&mut self, db: f64) -> fdo::Result<()> {
+        if !db.is_finite() {
+            return Err(fdo::Error::InvalidArgs(String::from("MixRampDb must be a finite number")));
+        }
+
+        self.mpd.request_data(&Command::mixrampdb(db)).await.map_err(|e| {
+            error!("Failed to set MixRamp threshold: {e}");
+            e
+        })?;
+
+        self.status.write().await.mixramp_db = db;
+        Ok(())
+    }
+}
+
+/// Returns the value of a property like Single corresponding to the state
+pub fn tri_state_to_str(state: TriState) -> &'static str {
+    match state {
+        TriState::Off => "off",
+        TriState::On => "on",
+        TriState::OneShot => "oneshot",
+    }
+}
+
+fn tri_state_from_str(state: &str) -> fdo::Result<TriState> {
+    match state {
+        "off" => Ok(TriState::Off),
+        "on" => Ok(TriState::On),
+        "oneshot" => Ok(TriState::OneShot),
+        _ => Err(fdo::Error::InvalidArgs(format!("`{state}` is not one of off, on or oneshot"))),
+    }
+}
@@ -1,42 +1,86 @@
 use async_std::channel::Receiver;
 use async_std::task::{JoinHandle, spawn};
 use log::warn;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use zbus::Connection;
-use zbus::zvariant::ObjectPath;
+use zbus::zvariant::{ObjectPath, Value};
 use zbus::{connection::Builder, object_server::InterfaceRef};
 
 use base::BaseInterface;
+use extensions::ExtensionsInterface;
+use notifications::Notifier;
+use outputs::OutputsInterface;
 use player::PlayerInterface;
+use playlists::PlaylistsInterface;
+use tracklist::TrackListInterface;
 
-use crate::client::{MPDClient, StateChanged};
+use crate::client::{MPDClient, Song, StateChanged};
+use crate::config::{Config, PlayerConfig, label_name};
+use crate::hooks::Hooks;
+use crate::statusbar::StatusBar;
 
 mod base;
+mod extensions;
+mod notifications;
+mod outputs;
 mod player;
+mod playlists;
+mod tracklist;
 
 const NAME: &str = "org.mpris.MediaPlayer2.mpd";
+const IDENTITY: &str = "Music Player Daemon";
 const PATH: &str = "/org/mpris/MediaPlayer2";
 const TRACKID_PATH_BASE: &str = "/org/musicpd/mpris/";
+/// Special trackid used by MPRIS to indicate the absence of a track
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
 
+/// Serves the MPRIS interface for the given client.
+///
+/// If the client belongs to a configured player, its label is appended to the bus name,
+/// so that multiple players can be served at once
 pub async fn serve(
-    connection: Arc<MPDClient>,
+    mpd: Arc<MPDClient>,
     recv: Receiver<StateChanged>,
+    player: Option<&PlayerConfig>,
+    status_bar: Option<StatusBar>,
 ) -> Result<(Connection, JoinHandle<()>), zbus::Error> {
-    let base = BaseInterface::new();
-    let player = PlayerInterface::new(connection).await;
+    let (name, identity) = match player {
+        Some(p) => (
+            format!("{NAME}.{}", label_name(&p.label)),
+            p.identity.clone().unwrap_or_else(|| format!("{IDENTITY} ({})", p.label)),
+        ),
+        None => (NAME.to_string(), IDENTITY.to_string()),
+    };
+
+    let hooks = Hooks::new(mpd.clone(), player.map(|p| p.label.as_str()));
+    let base = BaseInterface::new(identity);
+    let player = PlayerInterface::new(mpd.clone()).await;
+    let tracklist = TrackListInterface::new(mpd.clone()).await;
+    let playlists = PlaylistsInterface::new(mpd.clone()).await;
+    let extensions = ExtensionsInterface::new(mpd.clone()).await;
+    let outputs = OutputsInterface::new(mpd.clone()).await;
 
     let connection = Builder::session()?
-        .name(NAME)?
+        .name(name)?
         .serve_at(PATH, base)?
         .serve_at(PATH, player)?
+        .serve_at(PATH, tracklist)?
+        .serve_at(PATH, playlists)?
+        .serve_at(PATH, extensions)?
+        .serve_at(PATH, outputs)?
         .build()
         .await?;
 
+    let player_iface_ref = connection.object_server().interface(PATH).await?;
+    let notifier = Notifier::new(&connection, &mpd, player_iface_ref);
     let signal_connection = connection.clone();
+    if let Some(status_bar) = &status_bar {
+        status_bar.write().await;
+    }
 
     let task = spawn(async move {
         loop {
-            if let Err(err) = send_signals(&signal_connection, &recv).await {
+            if let Err(err) = send_signals(&signal_connection, &recv, &notifier, &hooks, status_bar.as_ref()).await {
                 warn!("D-Bus Change Signal Sender died, restarting: {err}");
             }
         }
@@ -53,14 +97,83 @@ fn path_to_id(path: &ObjectPath<'_>) -> Option<u32> {
     path.strip_prefix(TRACKID_PATH_BASE)?.parse().ok()
 }
 
-async fn send_signals(connection: &Connection, recv: &Receiver<StateChanged>) -> zbus::Result<()> {
+/// Converts a song into its MPRIS metadata map
+fn song_metadata(song: &Song, config: &Config) -> HashMap<&'static str, Value<'static>> {
+    let mut map = HashMap::new();
+    let song_url = format!("file://{}", config.music_directory.join(&song.uri).display());
+
+    map.insert("mpris:trackid", id_to_path(song.id).into());
+    map.insert("xesam:url", song_url.into());
+    let m = &mut map;
+
+    if let Some(duration) = song.duration {
+        m.insert("mpris:length", (duration.as_micros() as i64).into());
+    }
+    if let Some(rating) = song.rating {
+        let rating = f64::from(rating) / f64::from(config.rating_max);
+        m.insert("xesam:userRating", rating.clamp(0.0, 1.0).into());
+    }
+    if let Some(count) = song.play_count {
+        m.insert("xesam:useCount", (count.min(i32::MAX as u32) as i32).into());
+    }
+    if let Some(date) = song.date {
+        m.insert("xesam:contentCreated", format!("{date}-01-01T00:00+0000").into());
+    }
+
+    add_if_some(m, "mpris:artUrl", &song.cover);
+    add_if_some(m, "xesam:album", &song.album);
+    add_if_some(m, "xesam:discNumber", &song.disc);
+    add_if_some(m, "xesam:title", &song.title);
+    add_if_some(m, "xesam:trackNumber", &song.track);
+    add_if_not_empty(m, "xesam:artist", &song.artists);
+    add_if_not_empty(m, "xesam:albumArtist", &song.album_artists);
+    add_if_not_empty(m, "xesam:comment", &song.comments);
+    add_if_not_empty(m, "xesam:composer", &song.composers);
+    add_if_not_empty(m, "xesam:genre", &song.genres);
+
+    map
+}
+
+fn add_if_some<'k, 'v, T>(map: &mut HashMap<&'k str, Value<'v>>, k: &'k str, v: &Option<T>)
+where
+    T: Into<Value<'v>> + Clone,
+{
+    if let Some(value) = v {
+        map.insert(k, value.clone().into());
+    }
+}
+
+fn add_if_not_empty<'k, 'v, T>(map: &mut HashMap<&'k str, Value<'v>>, k: &'k str, v: &[T])
+where
+    T: zbus::zvariant::Type + Into<Value<'v>> + Clone,
+{
+    if !v.is_empty() {
+        map.insert(k, Value::Array(v.into()));
+    }
+}
+
+async fn send_signals(
+    connection: &Connection,
+    recv: &Receiver<StateChanged>,
+    notifier: &Notifier,
+    hooks: &Hooks,
+    status_bar: Option<&StatusBar>,
+) -> zbus::Result<()> {
     let object_server = connection.object_server();
     let player_iface_ref: InterfaceRef<PlayerInterface> = object_server.interface(PATH).await.unwrap();
+    let tracklist_iface_ref: InterfaceRef<TrackListInterface> = object_server.interface(PATH).await.unwrap();
+    let playlists_iface_ref: InterfaceRef<PlaylistsInterface> = object_server.interface(PATH).await.unwrap();
+    let extensions_iface_ref: InterfaceRef<ExtensionsInterface> = object_server.interface(PATH).await.unwrap();
+    let outputs_iface_ref: InterfaceRef<OutputsInterface> = object_server.interface(PATH).await.unwrap();
 
     loop {
         use StateChanged::*;
 
         let change = recv.recv().await.expect("Channel must always be open");
+        hooks.run(&change).await;
+        if let Some(status_bar) = status_bar {
+            status_bar.update(&change).await;
+        }
 
         let player_iface = player_iface_ref.get_mut().await;
         let player_ctxt = player_iface_ref.signal_emitter();
@@ -69,6 +182,9 @@ async fn send_signals(connection: &Connection, recv: &Receiver<StateChanged>) ->
             Position(ms) => {
                 PlayerInterface::seeked(player_ctxt, ms).await?;
             }
+            Sticker => {
+                player_iface.metadata_changed(player_ctxt).await?;
+            }
             Song(prev, next) => {
                 player_iface.metadata_changed(player_ctxt).await?;
                 if prev {
@@ -77,22 +193,85 @@ async fn send_signals(connection: &Connection, recv: &Receiver<StateChanged>) ->
                 if next {
                     player_iface.can_go_next_changed(player_ctxt).await?;
                 }
+                notifier.notify().await;
             }
             Playlist => {
-                // TODO implement tracklist interface
+                let mut tracklist_iface = tracklist_iface_ref.get_mut().await;
+                tracklist_iface.update_tracks(tracklist_iface_ref.signal_emitter()).await?;
+            }
+            StoredPlaylists => {
+                let mut playlists_iface = playlists_iface_ref.get_mut().await;
+                playlists_iface.update_playlists(playlists_iface_ref.signal_emitter()).await?;
             }
             PlayState => {
                 player_iface.playback_status_changed(player_ctxt).await?;
+                notifier.update().await;
             }
             Volume => {
                 player_iface.volume_changed(player_ctxt).await?;
             }
+            Mixer => {
+                let extensions_iface = extensions_iface_ref.get().await;
+                extensions_iface
+                    .can_set_volume_changed(extensions_iface_ref.signal_emitter())
+                    .await?;
+            }
             Repeat => {
                 player_iface.loop_status_changed(player_ctxt).await?;
             }
             Shuffle => {
                 player_iface.shuffle_changed(player_ctxt).await?;
             }
+            Connection => {
+                player_iface.can_control_changed(player_ctxt).await?;
+                player_iface.can_play_changed(player_ctxt).await?;
+                player_iface.can_pause_changed(player_ctxt).await?;
+                player_iface.can_seek_changed(player_ctxt).await?;
+                player_iface.can_go_next_changed(player_ctxt).await?;
+                player_iface.can_go_previous_changed(player_ctxt).await?;
+
+                let tracklist_iface = tracklist_iface_ref.get().await;
+                tracklist_iface
+                    .can_edit_tracks_changed(tracklist_iface_ref.signal_emitter())
+                    .await?;
+
+                let mut playlists_iface = playlists_iface_ref.get_mut().await;
+                playlists_iface.update_playlists(playlists_iface_ref.signal_emitter()).await?;
+            }
+            Single => {
+                let extensions_iface = extensions_iface_ref.get().await;
+                extensions_iface.single_changed(extensions_iface_ref.signal_emitter()).await?;
+            }
+            Consume => {
+                let extensions_iface = extensions_iface_ref.get().await;
+                extensions_iface
+                    .consume_changed(extensions_iface_ref.signal_emitter())
+                    .await?;
+            }
+            Crossfade => {
+                let extensions_iface = extensions_iface_ref.get().await;
+                extensions_iface
+                    .crossfade_changed(extensions_iface_ref.signal_emitter())
+                    .await?;
+            }
+            MixRampDb => {
+                let extensions_iface = extensions_iface_ref.get().await;
+                extensions_iface
+                    .mix_ramp_db_changed(extensions_iface_ref.signal_emitter())
+                    .await?;
+            }
+            ReplayGainMode => {
+                let extensions_iface = extensions_iface_ref.get().await;
+                extensions_iface
+                    .replay_gain_mode_changed(extensions_iface_ref.signal_emitter())
+                    .await?;
+            }
+            Outputs => {
+                let mut outputs_iface = outputs_iface_ref.get_mut().await;
+                outputs_iface.update_outputs(outputs_iface_ref.signal_emitter()).await?;
+            }
+            // not exposed over MPRIS
+            DatabaseUpdating | Message | Partition => {}
         }
     }
 }
@@ -0,0 +1,196 @@
+use async_std::sync::{Mutex, RwLock};
+use async_std::{stream::StreamExt, task::spawn};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use zbus::object_server::InterfaceRef;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::Value;
+use zbus::{Connection, proxy};
+
+use super::player::PlayerInterface;
+use crate::client::{MPDClient, PlayState, Status, render_template};
+use crate::config::config;
+
+const APP_NAME: &str = "mpdris";
+const ACTION_NEXT: &str = "next";
+const ACTION_PLAY_PAUSE: &str = "play-pause";
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    #[zbus(signal)]
+    fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+/// Shows a desktop notification whenever the song changes, see
+/// [the notification specification](https://specifications.freedesktop.org/notification-spec/latest/)
+pub struct Notifier {
+    connection: Connection,
+    /// The player interface the actions of notifications are routed to
+    player: InterfaceRef<PlayerInterface>,
+    /// Proxy of the notification server, only created once the first notification is shown
+    proxy: Mutex<Option<NotificationsProxy<'static>>>,
+    status: Arc<RwLock<Status>>,
+    /// ID of the last notification, reused so that a new notification replaces the previous one.
+    /// Reset to 0 once the notification is closed
+    id: Arc<AtomicU32>,
+}
+
+impl Notifier {
+    /// Creates the notifier. The notification server is only contacted once notifications are enabled
+    /// and the first notification is shown, so that mpdris works without one
+    pub fn new(connection: &Connection, mpd: &MPDClient, player: InterfaceRef<PlayerInterface>) -> Self {
+        Self {
+            connection: connection.clone(),
+            player,
+            proxy: Mutex::new(None),
+            status: mpd.get_status(),
+            id: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// Shows a notification for the current song, replacing the previous notification.
+    /// Does nothing if notifications are disabled or nothing is playing
+    pub async fn notify(&self) {
+        let (summary, body, timeout) = {
+            let c = config().read().await;
+            if !c.notifications {
+                return;
+            }
+            (
+                c.notification_summary.clone(),
+                c.notification_body.clone(),
+                c.notification_timeout,
+            )
+        };
+
+        let status = self.status.read().await;
+        let Some(song) = status.current_song.as_ref().filter(|_| status.state != PlayState::Stopped) else {
+            return;
+        };
+
+        let summary = render_template(&summary, song);
+        // the body may contain markup, so tag values have to be escaped
+        let body = escape_markup(&render_template(&body, song));
+        let pause = if status.state == PlayState::Playing { "Pause" } else { "Play" };
+        let actions = [ACTION_NEXT, "Next", ACTION_PLAY_PAUSE, pause];
+
+        let mut hints = HashMap::new();
+        if let Some(cover) = &song.cover {
+            hints.insert("image-path", Value::from(cover.as_ref()));
+        }
+
+        let proxy = match self.proxy().await {
+            Ok(proxy) => proxy,
+            Err(err) => {
+                warn!("Could not connect to the notification server: {err}");
+                return;
+            }
+        };
+
+        let id = self.id.load(Ordering::Relaxed);
+        match proxy
+            .notify(APP_NAME, id, "", &summary, &body, &actions, hints, timeout)
+            .await
+        {
+            Ok(id) => self.id.store(id, Ordering::Relaxed),
+            Err(err) => warn!("Could not show notification: {err}"),
+        }
+    }
+
+    /// Returns the proxy of the notification server. On first use, it is created and starts listening
+    /// for the actions of notifications, which are routed to the player interface
+    async fn proxy(&self) -> zbus::Result<NotificationsProxy<'static>> {
+        let mut proxy = self.proxy.lock().await;
+        if let Some(proxy) = &*proxy {
+            return Ok(proxy.clone());
+        }
+
+        let new = NotificationsProxy::builder(&self.connection)
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+
+        let mut actions = new.receive_action_invoked().await?;
+        let action_id = self.id.clone();
+        let player = self.player.clone();
+        spawn(async move {
+            while let Some(signal) = actions.next().await {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+                if args.id == 0 || args.id != action_id.load(Ordering::Relaxed) {
+                    continue;
+                }
+
+                debug!("notification action '{}' invoked", args.action_key);
+                let mut player = player.get_mut().await;
+                // errors are already logged by the player interface
+                let _ = match args.action_key.as_str() {
+                    ACTION_NEXT => player.next().await,
+                    ACTION_PLAY_PAUSE => player.play_pause().await,
+                    _ => Ok(()),
+                };
+            }
+        });
+
+        let mut closed = new.receive_notification_closed().await?;
+        let closed_id = self.id.clone();
+        spawn(async move {
+            while let Some(signal) = closed.next().await {
+                if let Ok(args) = signal.args() {
+                    let _ = closed_id.compare_exchange(args.id, 0, Ordering::Relaxed, Ordering::Relaxed);
+                }
+            }
+        });
+
+        *proxy = Some(new.clone());
+        Ok(new)
+    }
+
+    /// Updates the notification if it is still shown, so that e.g. its Play/Pause action matches the current state
+    pub async fn update(&self) {
+        if self.id.load(Ordering::Relaxed) != 0 {
+            self.notify().await;
+        }
+    }
+}
+
+/// Escapes the characters used by the markup notification servers may support in the body
+fn escape_markup(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_markup() {
+        assert_eq!(
+            escape_markup("Simon & Garfunkel <Live>"),
+            "Simon &amp; Garfunkel &lt;Live&gt;"
+        );
+    }
+}
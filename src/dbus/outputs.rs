@@ -0,0 +1,76 @@
+use log::{error, warn};
+use std::sync::Arc;
+use zbus::{fdo, interface, object_server::SignalEmitter};
+
+use crate::client::{ErrorKind, MPDClient, Output};
+
+/// D-Bus representation of an output: (id, name, plugin, enabled)
+type DBusOutput = (u32, String, String, bool);
+
+/// The audio outputs of MPD
+pub struct OutputsInterface {
+    mpd: Arc<MPDClient>,
+    /// Cached outputs, in the order MPD lists them
+    outputs: Vec<Output>,
+}
+
+impl OutputsInterface {
+    pub async fn new(connection: Arc<MPDClient>) -> Self {
+        Self {
+            mpd: connection,
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Fetches the outputs from MPD and emits PropertiesChanged if they changed
+    pub async fn update_outputs(&mut self, ctxt: &SignalEmitter<'_>) -> zbus::Result<()> {
+        let outputs = match self.mpd.get_outputs().await {
+            Ok(outputs) => outputs,
+            Err(err) if err.kind == ErrorKind::NotConnected => Vec::new(),
+            Err(err) => {
+                warn!("Could not get the outputs from MPD: {err}");
+                return Ok(());
+            }
+        };
+
+        if outputs == self.outputs {
+            return Ok(());
+        }
+
+        self.outputs = outputs;
+        self.outputs_changed(ctxt).await
+    }
+}
+
+#[interface(name = "org.mpdris.Outputs")]
+impl OutputsInterface {
+    async fn enable_output(&self, id: u32) -> fdo::Result<()> {
+        self.mpd.set_output_enabled(id, true).await.map_err(|err| {
+            error!("Failed to enable output {id}: {err}");
+            err.into()
+        })
+    }
+
+    async fn disable_output(&self, id: u32) -> fdo::Result<()> {
+        self.mpd.set_output_enabled(id, false).await.map_err(|err| {
+            error!("Failed to disable output {id}: {err}");
+            err.into()
+        })
+    }
+
+    async fn toggle_output(&self, id: u32) -> fdo::Result<()> {
+        self.mpd.toggle_output(id).await.map_err(|err| {
+            error!("Failed to toggle output {id}: {err}");
+            err.into()
+        })
+    }
+
+    /// All outputs as (id, name, plugin, enabled)
+    #[zbus(property)]
+    async fn outputs(&self) -> Vec<DBusOutput> {
+        self.outputs
+            .iter()
+            .map(|o| (o.id, o.name.to_string(), o.plugin.to_string(), o.enabled))
+            .collect()
+    }
+}
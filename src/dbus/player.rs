@@ -1,16 +1,18 @@
 use async_std::sync::RwLock;
 use log::{error, warn};
-use std::{collections::HashMap, ops::Add, sync::Arc, time::Duration};
+use std::{borrow::Cow, collections::HashMap, ops::Add, sync::Arc, time::Duration};
 use zbus::{
-    fdo, interface,
-    object_server::SignalEmitter,
+    fdo::{self, Properties},
+    interface,
+    object_server::{Interface, SignalEmitter},
     zvariant::{ObjectPath, Value},
 };
 
-use crate::client::{MPDClient, PlayState, Repeat, Status};
+use crate::client::{Command, CommandList, MPDClient, PlayState, Repeat, Status};
 use crate::config::config;
 
-use super::{id_to_path, path_to_id};
+use super::extensions::{ExtensionsInterface, tri_state_to_str};
+use super::{path_to_id, song_metadata};
 
 pub struct PlayerInterface {
     mpd: Arc<MPDClient>,
@@ -26,7 +28,7 @@ impl PlayerInterface {
 
 #[interface(name = "org.mpris.MediaPlayer2.Player")]
 impl PlayerInterface {
-    async fn next(&mut self) -> fdo::Result<()> {
+    pub async fn next(&mut self) -> fdo::Result<()> {
         let s = self.status.read().await;
 
         if let Some(next_id) = s.next_song {
@@ -48,14 +50,15 @@ impl PlayerInterface {
         let s = self.status.read().await;
 
         if s.playlist_length >= 1 {
-            let cmd = if s.state != PlayState::Playing {
-                "command_list_begin\nprevious\npause\ncommand_list_end"
+            let res = if s.state != PlayState::Playing {
+                let cmd = CommandList::new().push(Command::previous()).push(Command::pause(Some(true)));
+                self.mpd.request_data(&cmd).await.map(drop)
             } else {
-                "previous"
+                self.mpd.request_data(&Command::previous()).await.map(drop)
             };
 
-            match self.mpd.request_data(cmd).await {
-                Ok(_) => Ok(()),
+            match res {
+                Ok(()) => Ok(()),
                 Err(err) => {
                     error!("Failed to switch to previous song: {err}");
                     Err(err.into())
@@ -78,7 +81,7 @@ impl PlayerInterface {
         })
     }
 
-    async fn play_pause(&mut self) -> fdo::Result<()> {
+    pub async fn play_pause(&mut self) -> fdo::Result<()> {
         if !self.can_pause().await {
             return Err(fdo::Error::Failed(String::from(
                 "Attempted to toggle playback while CanPause is false",
@@ -173,36 +176,50 @@ impl PlayerInterface {
 
     #[zbus(property)]
     async fn loop_status(&self) -> &str {
-        match self.status.read().await.repeat {
-            Repeat::Off => "None",
-            Repeat::On => "Playlist",
-            Repeat::Single => "Track",
-        }
+        loop_status(self.status.read().await.repeat)
     }
 
     #[zbus(property)]
-    async fn set_loop_status(&mut self, loop_status: String) -> fdo::Result<()> {
+    async fn set_loop_status(
+        &mut self,
+        loop_status: String,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
         let (repeat, single) = match loop_status.as_str() {
-            "None" => (0u8, 0u8),
-            "Playlist" => (1, 0),
-            "Track" => (1, 1),
+            "None" => (false, false),
+            "Playlist" => (true, false),
+            "Track" => (true, true),
             _ => return Err(fdo::Error::InvalidArgs(format!("`{loop_status}` is not a valid loop status"))),
         };
 
-        let cmd = format!("command_list_begin\nrepeat {repeat}\nsingle {single}\ncommand_list_end");
+        let cmd = CommandList::new()
+            .push(Command::repeat(repeat))
+            .push(Command::single(single.into()));
         self.mpd.request_data(&cmd).await.map_err(|e| {
             error!("Failed to set loop status: {e}");
             e
         })?;
 
-        self.status.write().await.repeat = if single == 1 {
-            Repeat::Single
-        } else if repeat == 1 {
-            Repeat::On
-        } else {
-            Repeat::Off
+        // single is an option of the extensions interface as well, see ExtensionsInterface::set_single
+        let (old, new) = {
+            let mut s = self.status.write().await;
+            let old = s.single;
+            s.single = single.into();
+            s.repeat = if single {
+                Repeat::Single
+            } else if repeat {
+                Repeat::On
+            } else {
+                Repeat::Off
+            };
+            (old, s.single)
         };
 
+        // the next change in MPD does not differ from the cached status anymore, so it is signaled here
+        if old != new {
+            let changed = HashMap::from([("Single", Value::from(tri_state_to_str(new)))]);
+            Properties::properties_changed(&emitter, ExtensionsInterface::name(), changed, Cow::Borrowed(&[])).await?;
+        }
         Ok(())
     }
 
@@ -213,9 +230,7 @@ impl PlayerInterface {
 
     #[zbus(property)]
     async fn set_shuffle(&self, shuffle: bool) -> zbus::Result<()> {
-        let cmd = if shuffle { "random 1" } else { "random 0" };
-
-        self.mpd.request_data(cmd).await.map_err(|e| {
+        self.mpd.request_data(&Command::random(shuffle)).await.map_err(|e| {
             error!("Could not set shuffleing: {e}");
             Into::<fdo::Error>::into(e)
         })?;
@@ -227,62 +242,50 @@ impl PlayerInterface {
     #[zbus(property)]
     async fn metadata(&self) -> HashMap<&str, Value<'_>> {
         let s = self.status.read().await;
-        let c = config().read().await;
-
-        let mut map = HashMap::new();
 
-        if let Some(song) = &s.current_song {
-            let song_url = format!("file://{}", c.music_directory.join(&song.uri).display());
-
-            map.insert("mpris:trackid", id_to_path(song.id).into());
-            map.insert("xesam:url", song_url.into());
-            let m = &mut map;
+        match &s.current_song {
+            Some(song) => {
+                let mut map = song_metadata(song, &*config().read().await);
+                if let Some(duration) = s.duration {
+                    map.insert("mpris:length", (duration.as_micros() as i64).into());
+                }
 
-            if let Some(duration) = s.duration {
-                m.insert("mpris:length", (duration.as_micros() as i64).into());
+                map
             }
-            if let Some(date) = song.date {
-                m.insert("xesam:contentCreated", format!("{date}-01-01T00:00+0000").into());
-            }
-
-            add_if_some(m, "mpris:artUrl", &song.cover);
-            add_if_some(m, "xesam:album", &song.album);
-            add_if_some(m, "xesam:discNumber", &song.disc);
-            add_if_some(m, "xesam:title", &song.title);
-            add_if_some(m, "xesam:trackNumber", &song.track);
-            add_if_not_empty(m, "xesam:artist", &song.artists);
-            add_if_not_empty(m, "xesam:albumArtist", &song.album_artists);
-            add_if_not_empty(m, "xesam:comment", &song.comments);
-            add_if_not_empty(m, "xesam:composer", &song.composers);
-            add_if_not_empty(m, "xesam:genre", &song.genres);
+            None => HashMap::new(),
         }
-
-        map
     }
 
+    /// The volume between 0.0 and 1.0, or 0.0 if MPD has no mixer
     #[zbus(property)]
     async fn volume(&self) -> f64 {
-        self.status.read().await.volume as f64
+        volume_to_mpris(self.status.read().await.volume)
     }
 
+    /// Sets the volume, values outside of 0.0 to 1.0 get clamped.
+    /// Fails with NotSupported if MPD has no mixer
     #[zbus(property)]
     async fn set_volume(&self, volume: f64) -> zbus::Result<()> {
-        if !(0.0..=100.0).contains(&volume) {
-            return Err(fdo::Error::InvalidArgs(String::from("Volume must be between 0 and 100")).into());
+        if volume.is_nan() {
+            return Err(fdo::Error::InvalidArgs(String::from("Volume must be a number")).into());
+        }
+        if self.status.read().await.volume.is_none() {
+            return Err(fdo::Error::NotSupported(String::from("MPD has no mixer, the volume cannot be changed")).into());
         }
 
-        self.mpd.request_data(&format!("setvol {volume:.0}")).await.map_err(|e| {
+        let volume = volume_from_mpris(volume);
+        self.mpd.request_data(&Command::setvol(volume)).await.map_err(|e| {
             error!("Could not set volume: {e}");
             Into::<fdo::Error>::into(e)
         })?;
 
-        self.status.write().await.volume = volume as u8;
+        self.status.write().await.volume = Some(volume);
         Ok(())
     }
 
     #[zbus(property)]
     async fn position(&self) -> fdo::Result<i64> {
-        self.mpd.update_status().await?;
+        self.mpd.update_elapsed().await?;
         Ok(self.status.read().await.elapsed.unwrap_or(Duration::ZERO).as_micros() as i64)
     }
 
@@ -312,49 +315,71 @@ impl PlayerInterface {
 
     #[zbus(property)]
     async fn can_go_next(&self) -> bool {
-        self.status.read().await.next_song.is_some()
+        self.mpd.is_connected() && self.status.read().await.next_song.is_some()
     }
 
     #[zbus(property)]
     async fn can_go_previous(&self) -> bool {
-        self.status.read().await.playlist_length > 1
+        self.mpd.is_connected() && self.status.read().await.playlist_length > 1
     }
 
     #[zbus(property)]
     async fn can_play(&self) -> bool {
-        self.status.read().await.current_song.is_some()
+        self.mpd.is_connected() && self.status.read().await.current_song.is_some()
     }
 
     #[zbus(property)]
     async fn can_pause(&self) -> bool {
-        self.status.read().await.current_song.is_some()
+        self.mpd.is_connected() && self.status.read().await.current_song.is_some()
     }
 
     #[zbus(property)]
     async fn can_seek(&self) -> bool {
-        self.status.read().await.current_song.is_some()
+        self.mpd.is_connected() && self.status.read().await.current_song.is_some()
     }
 
     #[zbus(property)]
     async fn can_control(&self) -> bool {
-        true
+        self.mpd.is_connected()
     }
 }
 
-fn add_if_some<'k, 'v, T>(map: &mut HashMap<&'k str, Value<'v>>, k: &'k str, v: &Option<T>)
-where
-    T: Into<Value<'v>> + Clone,
-{
-    if let Some(value) = v {
-        map.insert(k, value.clone().into());
+/// Returns the MPRIS loop status corresponding to the repeat mode
+pub fn loop_status(repeat: Repeat) -> &'static str {
+    match repeat {
+        Repeat::Off => "None",
+        Repeat::On => "Playlist",
+        Repeat::Single => "Track",
     }
 }
 
-fn add_if_not_empty<'k, 'v, T>(map: &mut HashMap<&'k str, Value<'v>>, k: &'k str, v: &[T])
-where
-    T: zbus::zvariant::Type + Into<Value<'v>> + Clone,
-{
-    if !v.is_empty() {
-        map.insert(k, Value::Array(v.into()));
+/// Converts the volume of MPD in percent to the MPRIS volume between 0.0 and 1.0, 0.0 if MPD has no mixer
+fn volume_to_mpris(volume: Option<u8>) -> f64 {
+    volume.map_or(0.0, |v| f64::from(v) / 100.0)
+}
+
+/// Converts the MPRIS volume to the volume of MPD in percent, values outside of 0.0 to 1.0 get clamped
+fn volume_from_mpris(volume: f64) -> u8 {
+    (volume.clamp(0.0, 1.0) * 100.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_to_mpris() {
+        assert_eq!(volume_to_mpris(Some(0)), 0.0);
+        assert_eq!(volume_to_mpris(Some(40)), 0.4);
+        assert_eq!(volume_to_mpris(Some(100)), 1.0);
+        assert_eq!(volume_to_mpris(None), 0.0);
+    }
+
+    #[test]
+    fn test_volume_from_mpris() {
+        assert_eq!(volume_from_mpris(0.4), 40);
+        assert_eq!(volume_from_mpris(0.255), 26);
+        assert_eq!(volume_from_mpris(1.5), 100);
+        assert_eq!(volume_from_mpris(-0.5), 0);
     }
 }
@@ -0,0 +1,172 @@
+use log::{error, warn};
+use std::{fmt::Write, mem::replace, sync::Arc};
+use zbus::{fdo, interface, object_server::SignalEmitter, zvariant::ObjectPath};
+
+use crate::client::{ErrorKind, MPDClient, Playlist};
+
+const PLAYLIST_PATH_BASE: &str = "/org/musicpd/mpris/playlists/";
+
+/// MPRIS representation of a playlist: (id, name, icon)
+type MprisPlaylist = (ObjectPath<'static>, String, String);
+
+pub struct PlaylistsInterface {
+    mpd: Arc<MPDClient>,
+    /// Cached stored playlists, in the order MPD lists them
+    playlists: Vec<Playlist>,
+    /// Name of the playlist that was last activated
+    active: Option<Arc<str>>,
+}
+
+impl PlaylistsInterface {
+    pub async fn new(connection: Arc<MPDClient>) -> Self {
+        // the playlists are fetched whenever the connection to MPD is established or lost
+        Self {
+            mpd: connection,
+            playlists: Vec::new(),
+            active: None,
+        }
+    }
+
+    /// Fetches the stored playlists from MPD and emits PlaylistChanged for every playlist
+    /// that was added or modified. The playlists are empty while disconnected
+    pub async fn update_playlists(&mut self, ctxt: &SignalEmitter<'_>) -> zbus::Result<()> {
+        let playlists = match self.mpd.get_playlists().await {
+            Ok(playlists) => playlists,
+            // no playlists can be activated while the connection to MPD is lost
+            Err(err) if err.kind == ErrorKind::NotConnected => Vec::new(),
+            Err(err) => {
+                warn!("Could not get the stored playlists from MPD: {err}");
+                return Ok(());
+            }
+        };
+
+        let old = replace(&mut self.playlists, playlists);
+
+        for playlist in self.playlists.iter().filter(|p| !old.contains(p)) {
+            Self::playlist_changed(ctxt, to_mpris(playlist)).await?;
+        }
+
+        if old.len() != self.playlists.len() {
+            self.playlist_count_changed(ctxt).await?;
+        }
+
+        if let Some(active) = &self.active
+            && !self.playlists.iter().any(|p| p.name == *active)
+        {
+            self.active = None;
+            self.active_playlist_changed(ctxt).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Playlists")]
+impl PlaylistsInterface {
+    async fn activate_playlist(
+        &mut self,
+        playlist_id: ObjectPath<'_>,
+        #[zbus(signal_emitter)] ctxt: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        let Some(playlist) = path_to_name(&playlist_id).and_then(|name| self.playlists.iter().find(|p| *p.name == name)) else {
+            return Err(fdo::Error::InvalidArgs(format!("`{playlist_id}` is not a valid playlist")));
+        };
+
+        self.mpd.load_playlist(&playlist.name).await.map_err(|err| {
+            error!("Failed to activate playlist: {err}");
+            fdo::Error::from(err)
+        })?;
+
+        self.active = Some(Arc::clone(&playlist.name));
+        self.active_playlist_changed(&ctxt).await?;
+
+        Ok(())
+    }
+
+    async fn get_playlists(
+        &self,
+        index: u32,
+        max_count: u32,
+        order: &str,
+        reverse_order: bool,
+    ) -> fdo::Result<Vec<MprisPlaylist>> {
+        let mut playlists: Vec<&Playlist> = self.playlists.iter().collect();
+
+        match order {
+            "Alphabetical" => playlists.sort_by(|a, b| a.name.cmp(&b.name)),
+            // ISO 8601 timestamps can be compared lexicographically
+            "ModifiedDate" => playlists.sort_by(|a, b| a.last_modified.cmp(&b.last_modified)),
+            "UserDefined" => {}
+            _ => return Err(fdo::Error::InvalidArgs(format!("`{order}` is not a supported ordering"))),
+        }
+
+        if reverse_order {
+            playlists.reverse();
+        }
+
+        Ok(playlists
+            .into_iter()
+            .skip(index as usize)
+            .take(max_count as usize)
+            .map(to_mpris)
+            .collect())
+    }
+
+    #[zbus(signal)]
+    async fn playlist_changed(ctxt: &SignalEmitter<'_>, playlist: MprisPlaylist) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    async fn playlist_count(&self) -> u32 {
+        self.playlists.len() as u32
+    }
+
+    #[zbus(property)]
+    async fn orderings(&self) -> &[&str] {
+        &["Alphabetical", "ModifiedDate", "UserDefined"]
+    }
+
+    #[zbus(property)]
+    async fn active_playlist(&self) -> (bool, MprisPlaylist) {
+        match self
+            .active
+            .as_ref()
+            .and_then(|name| self.playlists.iter().find(|p| p.name == *name))
+        {
+            Some(playlist) => (true, to_mpris(playlist)),
+            None => (
+                false,
+                (ObjectPath::from_static_str_unchecked("/"), String::new(), String::new()),
+            ),
+        }
+    }
+}
+
+fn to_mpris(playlist: &Playlist) -> MprisPlaylist {
+    (name_to_path(&playlist.name), playlist.name.to_string(), String::new())
+}
+
+/// Converts a playlist name into an object path.
+/// Because playlist names may contain characters not allowed in object paths, the name is hex encoded
+fn name_to_path(name: &str) -> ObjectPath<'static> {
+    let mut path = String::with_capacity(PLAYLIST_PATH_BASE.len() + name.len() * 2);
+    path.push_str(PLAYLIST_PATH_BASE);
+    for b in name.bytes() {
+        let _ = write!(path, "{b:02x}");
+    }
+
+    ObjectPath::try_from(path).expect("should always create a valid path")
+}
+
+fn path_to_name(path: &ObjectPath<'_>) -> Option<String> {
+    let hex = path.strip_prefix(PLAYLIST_PATH_BASE)?;
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    String::from_utf8(bytes).ok()
+}
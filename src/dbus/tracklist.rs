@@ -0,0 +1,278 @@
+use async_std::sync::RwLock;
+use log::{error, warn};
+use std::{collections::HashMap, collections::HashSet, mem::replace, path::Path, sync::Arc};
+use zbus::{
+    fdo, interface,
+    object_server::SignalEmitter,
+    zvariant::{ObjectPath, Value},
+};
+
+use crate::client::{ErrorKind, MPDClient, Status};
+use crate::config::config;
+
+use super::{NO_TRACK, id_to_path, path_to_id, song_metadata};
+
+pub struct TrackListInterface {
+    mpd: Arc<MPDClient>,
+    status: Arc<RwLock<Status>>,
+    /// Cached ids of the songs in the queue, in the order they are played in
+    tracks: Vec<u32>,
+}
+
+impl TrackListInterface {
+    pub async fn new(connection: Arc<MPDClient>) -> Self {
+        let status = connection.get_status();
+
+        // the queue is fetched together with the rest of the status after connecting to MPD
+        Self {
+            mpd: connection,
+            status,
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Fetches the queue from MPD and emits the signals describing how it changed.
+    ///
+    /// When songs were only added or removed, TrackAdded and TrackRemoved are emitted,
+    /// otherwise the whole tracklist is replaced
+    pub async fn update_tracks(&mut self, ctxt: &SignalEmitter<'_>) -> zbus::Result<()> {
+        let tracks = match self.mpd.get_queue().await {
+            Ok(tracks) => tracks,
+            // the queue is shown as empty while the connection to MPD is lost
+            Err(err) if err.kind == ErrorKind::NotConnected => Vec::new(),
+            Err(err) => {
+                warn!("Could not get the queue from MPD: {err}");
+                return Ok(());
+            }
+        };
+
+        if tracks == self.tracks {
+            return Ok(());
+        }
+
+        let old = replace(&mut self.tracks, tracks);
+        let old_ids: HashSet<u32> = old.iter().copied().collect();
+        let new_ids: HashSet<u32> = self.tracks.iter().copied().collect();
+
+        self.tracks_invalidate(ctxt).await?;
+
+        // if the songs present in both queues are in a different order, songs were moved,
+        // which cannot be expressed through TrackAdded & TrackRemoved
+        let kept_old = old.iter().filter(|id| new_ids.contains(id));
+        let kept_new = self.tracks.iter().filter(|id| old_ids.contains(id));
+        if !kept_old.eq(kept_new) {
+            let tracks = self.tracks.iter().map(|id| id_to_path(*id)).collect();
+            return Self::track_list_replaced(ctxt, tracks, self.current_track().await).await;
+        }
+
+        for id in old.iter().filter(|id| !new_ids.contains(id)) {
+            Self::track_removed(ctxt, id_to_path(*id)).await?;
+        }
+
+        for (i, id) in self.tracks.iter().enumerate() {
+            if old_ids.contains(id) {
+                continue;
+            }
+
+            let after = if i == 0 { no_track() } else { id_to_path(self.tracks[i - 1]) };
+            match self.song_metadata(*id).await {
+                Ok(metadata) => Self::track_added(ctxt, metadata, after).await?,
+                Err(err) => warn!("Could not get metadata of added song {id}: {err}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn current_track(&self) -> ObjectPath<'static> {
+        match &self.status.read().await.current_song {
+            Some(song) => id_to_path(song.id),
+            None => no_track(),
+        }
+    }
+
+    async fn song_metadata(&self, id: u32) -> fdo::Result<HashMap<&'static str, Value<'static>>> {
+        let song = self.mpd.get_song(id).await?;
+
+        Ok(song_metadata(&song, &*config().read().await))
+    }
+
+    /// Converts an uri into the form MPD expects, see [mpd_uri]
+    async fn to_mpd_uri(uri: String) -> String {
+        mpd_uri(uri, &config().read().await.music_directory)
+    }
+}
+
+/// Converts an uri into the form MPD expects, i.e. relative to the music directory if possible.
+/// `file://` uris are decoded, other uris are kept as is
+fn mpd_uri(uri: String, music_directory: &Path) -> String {
+    let Some(path) = uri.strip_prefix("file://").and_then(percent_decode) else {
+        return uri;
+    };
+
+    match Path::new(&path).strip_prefix(music_directory) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => format!("file://{path}"),
+    }
+}
+
+/// Decodes the percent-encoded characters of an uri, returns None if the result is not valid UTF-8
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        match tail {
+            [hi, lo, tail @ ..] if byte == b'%' && hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+                let hex = [*hi, *lo];
+                bytes.push(u8::from_str_radix(str::from_utf8(&hex).ok()?, 16).ok()?);
+                rest = tail;
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.TrackList")]
+impl TrackListInterface {
+    async fn get_tracks_metadata(
+        &self,
+        track_ids: Vec<ObjectPath<'_>>,
+    ) -> fdo::Result<Vec<HashMap<&'static str, Value<'static>>>> {
+        let mut metadata = Vec::with_capacity(track_ids.len());
+
+        for id in track_ids.iter().filter_map(path_to_id) {
+            if !self.tracks.contains(&id) {
+                continue;
+            }
+
+            metadata.push(self.song_metadata(id).await.inspect_err(|err| {
+                error!("Failed to get metadata of song {id}: {err}");
+            })?);
+        }
+
+        Ok(metadata)
+    }
+
+    async fn add_track(&self, uri: String, after_track: ObjectPath<'_>, set_as_current: bool) -> fdo::Result<()> {
+        let pos = if after_track.as_str() == NO_TRACK {
+            0
+        } else {
+            let idx = path_to_id(&after_track).and_then(|id| self.tracks.iter().position(|t| *t == id));
+            match idx {
+                Some(idx) => idx as u32 + 1,
+                None => {
+                    return Err(fdo::Error::InvalidArgs(format!(
+                        "`{after_track}` is not part of the tracklist"
+                    )));
+                }
+            }
+        };
+
+        let id = self
+            .mpd
+            .add_song(&Self::to_mpd_uri(uri).await, Some(pos))
+            .await
+            .map_err(|err| {
+                error!("Failed to add track: {err}");
+                fdo::Error::from(err)
+            })?;
+
+        if set_as_current {
+            self.mpd.goto_song(id).await.map_err(|err| {
+                error!("Failed to play added track: {err}");
+                fdo::Error::from(err)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    async fn remove_track(&self, track_id: ObjectPath<'_>) -> fdo::Result<()> {
+        let Some(id) = path_to_id(&track_id).filter(|id| self.tracks.contains(id)) else {
+            return Ok(());
+        };
+
+        self.mpd.remove_song(id).await.map_err(|err| {
+            error!("Failed to remove track: {err}");
+            err.into()
+        })
+    }
+
+    async fn go_to(&self, track_id: ObjectPath<'_>) -> fdo::Result<()> {
+        let Some(id) = path_to_id(&track_id).filter(|id| self.tracks.contains(id)) else {
+            return Ok(());
+        };
+
+        self.mpd.goto_song(id).await.map_err(|err| {
+            error!("Failed to go to track: {err}");
+            err.into()
+        })
+    }
+
+    #[zbus(signal)]
+    async fn track_list_replaced(
+        ctxt: &SignalEmitter<'_>,
+        tracks: Vec<ObjectPath<'_>>,
+        current_track: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn track_added(
+        ctxt: &SignalEmitter<'_>,
+        metadata: HashMap<&str, Value<'_>>,
+        after_track: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn track_removed(ctxt: &SignalEmitter<'_>, track_id: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(property(emits_changed_signal = "invalidates"))]
+    async fn tracks(&self) -> Vec<ObjectPath<'_>> {
+        self.tracks.iter().map(|id| id_to_path(*id)).collect()
+    }
+
+    #[zbus(property)]
+    async fn can_edit_tracks(&self) -> bool {
+        self.mpd.is_connected()
+    }
+}
+
+fn no_track() -> ObjectPath<'static> {
+    ObjectPath::from_static_str_unchecked(NO_TRACK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mpd_uri() {
+        let music = Path::new("/music");
+
+        assert_eq!(mpd_uri(String::from("file:///music/a/b.flac"), music), "a/b.flac");
+        assert_eq!(mpd_uri(String::from("file:///music2/a.flac"), music), "file:///music2/a.flac");
+        assert_eq!(
+            mpd_uri(String::from("http://radio.lan/stream"), music),
+            "http://radio.lan/stream"
+        );
+    }
+
+    #[test]
+    fn test_mpd_uri_percent_encoded() {
+        let music = Path::new("/music");
+
+        assert_eq!(
+            mpd_uri(String::from("file:///music/Lena%20Raine/K%C3%BCche.flac"), music),
+            "Lena Raine/Küche.flac"
+        );
+        assert_eq!(percent_decode("100%25%"), Some(String::from("100%%")));
+        assert_eq!(percent_decode("%zz%4"), Some(String::from("%zz%4")));
+        assert_eq!(percent_decode("%ff"), None);
+    }
+}
@@ -0,0 +1,187 @@
+use async_process::{Command, Stdio};
+use async_std::{future::timeout, task::spawn};
+use log::{debug, error, info, warn};
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::client::{MPDClient, StateChanged, Status};
+use crate::config::{HooksConfig, config};
+
+/// An event a hook can be run on, see [HooksConfig]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    SongChanged,
+    PlayState,
+    Volume,
+    Connected,
+    Disconnected,
+}
+
+impl HookEvent {
+    /// Returns the name of the event as used in the config
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SongChanged => "song_changed",
+            Self::PlayState => "play_state",
+            Self::Volume => "volume",
+            Self::Connected => "connected",
+            Self::Disconnected => "disconnected",
+        }
+    }
+
+    /// Returns the command configured for the event
+    fn command(self, hooks: &HooksConfig) -> Option<&str> {
+        match self {
+            Self::SongChanged => hooks.song_changed.as_deref(),
+            Self::PlayState => hooks.play_state.as_deref(),
+            Self::Volume => hooks.volume.as_deref(),
+            Self::Connected => hooks.connected.as_deref(),
+            Self::Disconnected => hooks.disconnected.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Runs the configured hooks of a player whenever its state changes
+pub struct Hooks {
+    mpd: Arc<MPDClient>,
+    /// Label of the player, passed to the hooks as `MPDRIS_PLAYER`
+    label: String,
+}
+
+impl Hooks {
+    pub fn new(mpd: Arc<MPDClient>, label: Option<&str>) -> Self {
+        Self {
+            mpd,
+            label: label.unwrap_or_default().to_string(),
+        }
+    }
+
+    /// Runs the hook belonging to the change in the background, if one is configured
+    pub async fn run(&self, change: &StateChanged) {
+        let event = match change {
+            StateChanged::Song(..) => HookEvent::SongChanged,
+            StateChanged::PlayState => HookEvent::PlayState,
+            StateChanged::Volume => HookEvent::Volume,
+            StateChanged::Connection if self.mpd.is_connected() => HookEvent::Connected,
+            StateChanged::Connection => HookEvent::Disconnected,
+            _ => return,
+        };
+
+        let (command, limit) = {
+            let c = config().read().await;
+            match event.command(&c.hooks) {
+                Some(command) if !command.is_empty() => (command.to_string(), Duration::from_secs(c.hooks.timeout)),
+                _ => return,
+            }
+        };
+
+        let mut env = environment(&*self.mpd.get_status().read().await);
+        env.push(("MPDRIS_EVENT", event.to_string()));
+        env.push(("MPDRIS_PLAYER", self.label.clone()));
+
+        spawn(run_hook(event, command, env, limit));
+    }
+}
+
+/// Runs the command using `sh -c` and logs its output. The command is killed once the limit is exceeded
+async fn run_hook(event: HookEvent, command: String, env: Vec<(&'static str, String)>, limit: Duration) {
+    debug!("running hook {event}: {command}");
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(&command).envs(env).stdin(Stdio::null()).kill_on_drop(true);
+
+    let output = match timeout(limit, cmd.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(err)) => {
+            error!("Failed to run hook {event}: {err}");
+            return;
+        }
+        Err(_) => {
+            warn!("Hook {event} did not finish within {}s and was killed", limit.as_secs());
+            return;
+        }
+    };
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        info!("hook {event}: {line}");
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        warn!("hook {event}: {line}");
+    }
+    if !output.status.success() {
+        warn!("Hook {event} exited with {}", output.status);
+    }
+}
+
+/// Returns the environment variables describing the status passed to every hook
+fn environment(status: &Status) -> Vec<(&'static str, String)> {
+    let song = status.current_song.as_ref();
+    let join = |tags: Option<&Vec<Arc<str>>>| tags.map(|t| t.join(", ")).unwrap_or_default();
+    let cover = song.and_then(|s| s.cover.as_deref()).map(|c| c.trim_start_matches("file://"));
+
+    vec![
+        ("MPDRIS_STATE", status.state.as_str().to_string()),
+        ("MPDRIS_VOLUME", or_empty(status.volume)),
+        ("MPDRIS_TITLE", or_empty(song.and_then(|s| s.title.as_ref()))),
+        ("MPDRIS_ARTIST", join(song.map(|s| &s.artists))),
+        ("MPDRIS_ALBUM", or_empty(song.and_then(|s| s.album.as_ref()))),
+        ("MPDRIS_ALBUMARTIST", join(song.map(|s| &s.album_artists))),
+        ("MPDRIS_GENRE", join(song.map(|s| &s.genres))),
+        ("MPDRIS_DATE", or_empty(song.and_then(|s| s.date))),
+        ("MPDRIS_TRACK", or_empty(song.and_then(|s| s.track))),
+        ("MPDRIS_FILE", or_empty(song.map(|s| s.uri.display()))),
+        ("MPDRIS_COVER", or_empty(cover)),
+        (
+            "MPDRIS_DURATION",
+            or_empty(song.and_then(|s| s.duration).map(|d| d.as_secs())),
+        ),
+    ]
+}
+
+/// Converts the value to a string, or returns an empty string if there is none
+fn or_empty<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{PlayState, Song};
+
+    fn get<'a>(env: &'a [(&str, String)], key: &str) -> &'a str {
+        env.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str()).unwrap()
+    }
+
+    #[test]
+    fn test_environment() {
+        let mut song = Song::test_song();
+        song.cover = Some("file:///covers/Celeste.png".into());
+
+        let mut status = Status::new();
+        status.state = PlayState::Playing;
+        status.volume = Some(80);
+        status.current_song = Some(song);
+        let env = environment(&status);
+
+        assert_eq!(get(&env, "MPDRIS_STATE"), "playing");
+        assert_eq!(get(&env, "MPDRIS_VOLUME"), "80");
+        assert_eq!(get(&env, "MPDRIS_TITLE"), "Resurrections");
+        assert_eq!(get(&env, "MPDRIS_ARTIST"), "Lena Raine, 2 Mello");
+        assert_eq!(get(&env, "MPDRIS_ALBUM"), "Celeste");
+        assert_eq!(get(&env, "MPDRIS_FILE"), "Celeste/Resurrections.mp3");
+        assert_eq!(get(&env, "MPDRIS_COVER"), "/covers/Celeste.png");
+    }
+
+    #[test]
+    fn test_environment_without_song() {
+        let env = environment(&Status::new());
+
+        assert_eq!(get(&env, "MPDRIS_STATE"), "stopped");
+        assert_eq!(get(&env, "MPDRIS_TITLE"), "");
+    }
+}
@@ -6,15 +6,20 @@ use std::{env, io, path::PathBuf, process::exit};
 
 use signal_hook::{consts::TERM_SIGNALS, flag, iterator::Signals, low_level::emulate_default_handler};
 
-use crate::args::Args;
+use crate::args::{Args, Subcommand};
 use crate::client::MPDClient;
+use crate::client::validate_status_template;
 use crate::config::{CONFIG, Config, config};
+use crate::statusbar::StatusBar;
 use util::notify::{Systemd, monotonic_time};
 
 mod args;
 mod client;
 mod config;
+mod ctl;
 mod dbus;
+mod hooks;
+mod statusbar;
 mod util;
 
 #[rustfmt::skip]
@@ -23,16 +28,31 @@ static HOME_DIR: LazyLock<PathBuf> = LazyLock::new(|| env::home_dir().expect("th
 
 #[cfg(target_os = "linux")]
 fn main() {
-    let args: Args = argh::from_env();
+    let args: Args = args::from_env();
 
     if args.version {
         println!("{}", VERSION_STR);
         exit(EXIT_SUCCESS);
     }
 
-    // there's no reason to init the logger if we close stdin & stdout
-    if !args.daemon || args.service {
-        util::init_logger(args.level);
+    if let Some(Subcommand::Ctl(ctl)) = &args.command {
+        block_on(ctl::run(ctl)).unwrap_or_else(|err| {
+            eprintln!("Could not control the player: {err}");
+            exit(EXIT_FAILURE);
+        });
+        exit(EXIT_SUCCESS);
+    }
+
+    // there's no reason to init the logger if we close stdin & stdout,
+    // but checking the config exits before forking and has to report its errors
+    if !args.daemon || args.service || args.check_config || args.print_config {
+        // the status or config is written to stdout, so log messages must not be
+        let stderr_only = args.print_config || (args.status_format.is_some() && args.status_output.is_none());
+        util::init_logger(args.level, stderr_only);
+    }
+
+    if args.check_config || args.print_config {
+        block_on(check_config(&args));
     }
 
     if args.daemon && !args.service {
@@ -67,15 +87,37 @@ async fn __main(args: Args) {
         CONFIG.set(config.into()).expect("CONFIG should not have been written to");
     }
 
+    if let Err(placeholder) = validate_status_template(&args.status_template) {
+        warn!("Status template contains unknown placeholder `{{{placeholder}}}`");
+    }
+
     // Main app here
-    let (conn, recv) = MPDClient::new()
-        .await
-        .unwrap_or_else(|e| panic!("Could not connect to mpd server: {e}"));
-    let conn = Arc::new(conn);
+    // one player for the configured server, or one for each configured player
+    let players = match config().read().await.players() {
+        players if players.is_empty() => vec![None],
+        players => players.into_iter().map(Some).collect(),
+    };
 
-    let _interface = dbus::serve(conn.clone(), recv)
-        .await
-        .unwrap_or_else(|err| panic!("Could not serve the dbus interface: {err}"));
+    let mut clients = Vec::new();
+    let mut _interfaces = Vec::new();
+    for player in players {
+        // connects in the background, so the interface is available even while MPD is not
+        let (conn, recv) = MPDClient::new(player.as_ref().map(|p| p.label.clone()));
+        let conn = Arc::new(conn);
+
+        // the status is only written for the first player
+        let status_bar = args
+            .status_format
+            .filter(|_| clients.is_empty())
+            .map(|format| StatusBar::new(conn.clone(), format, args.status_template.clone(), args.status_output.clone()));
+
+        let interface = dbus::serve(conn.clone(), recv, player.as_ref(), status_bar)
+            .await
+            .unwrap_or_else(|err| panic!("Could not serve the dbus interface: {err}"));
+
+        clients.push(conn);
+        _interfaces.push(interface);
+    }
 
     let libsystemd = if args.service {
         Some(Systemd::new().expect("failed to load libsystemd"))
@@ -101,10 +143,11 @@ async fn __main(args: Args) {
                     Ok(c) => {
                         *config().write().await = c;
 
-                        conn.reconnect().await.unwrap_or_else(|err| {
-                            error!("Could not reconnect to mpd, quitting: {err}");
-                            handle.close();
-                        });
+                        for conn in &clients {
+                            conn.reconnect().await.unwrap_or_else(|err| {
+                                error!("Could not reconnect to mpd, continuing with the old connection: {err}");
+                            });
+                        }
 
                         if let Some(libsystemd) = &libsystemd {
                             libsystemd.notify("READY=1");
@@ -136,6 +179,32 @@ async fn __main(args: Args) {
     }
 }
 
+/// Loads and validates the config for `--check-config` and `--print-config`, then exits
+async fn check_config(args: &Args) -> ! {
+    let config = Config::load_config(&args.config, args).await.unwrap_or_else(|err| {
+        error!("Invalid config: {err}");
+        exit(EXIT_FAILURE);
+    });
+    config.check_paths().unwrap_or_else(|err| {
+        error!("Invalid config: {err}");
+        exit(EXIT_FAILURE);
+    });
+
+    if args.print_config {
+        match config.to_annotated_string(&args.config, args).await {
+            Ok(data) => print!("{data}"),
+            Err(err) => {
+                error!("Could not print the config: {err}");
+                exit(EXIT_FAILURE);
+            }
+        }
+    } else {
+        info!("The config is valid");
+    }
+
+    exit(EXIT_SUCCESS)
+}
+
 /// Subscribes to exit signals
 /// If is_daemon is true will add SIGHUP signal to returned Signals
 fn get_signals(is_daemon: bool) -> io::Result<Signals> {
@@ -0,0 +1,173 @@
+use async_std::channel::{Receiver, Sender, unbounded};
+use async_std::fs::{File, OpenOptions};
+use async_std::io::{self, WriteExt};
+use async_std::task::spawn;
+use log::{debug, warn};
+use std::{path::PathBuf, str::FromStr, sync::Arc};
+
+use crate::client::{MPDClient, StateChanged, Status, render_status_template};
+
+/// The format of the lines written by a [StatusBar]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFormat {
+    /// A JSON object as expected by the custom module of waybar
+    Json,
+    /// The rendered template only, e.g. for polybar
+    Text,
+}
+
+impl FromStr for StatusFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "text" => Ok(Self::Text),
+            _ => Err(format!("Unknown status format `{s}`, expected json or text")),
+        }
+    }
+}
+
+/// Writes a line describing the status of a player whenever it changes, to be read by status bars
+pub struct StatusBar {
+    mpd: Arc<MPDClient>,
+    format: StatusFormat,
+    template: String,
+    /// Sends the lines to the task writing them, so that a FIFO without reader does not block the caller
+    sender: Sender<String>,
+}
+
+impl StatusBar {
+    /// Creates the status bar writing to the file or FIFO at output, or to stdout if output is None
+    pub fn new(mpd: Arc<MPDClient>, format: StatusFormat, template: String, output: Option<PathBuf>) -> Self {
+        let (sender, recv) = unbounded();
+        spawn(write_lines(recv, output));
+
+        Self {
+            mpd,
+            format,
+            template,
+            sender,
+        }
+    }
+
+    /// Writes the current status if the change affects it
+    pub async fn update(&self, change: &StateChanged) {
+        use StateChanged::*;
+
+        if matches!(change, Song(..) | PlayState | Volume | Connection | Sticker) {
+            self.write().await;
+        }
+    }
+
+    /// Writes the current status
+    pub async fn write(&self) {
+        let line = status_line(self.format, &self.template, &*self.mpd.get_status().read().await);
+        self.sender.send(line).await.expect("Status bar writer should never exit");
+    }
+}
+
+/// Writes every line received to output, skipping lines equal to the previous one.
+/// Lines received while waiting for the output are skipped in favor of the latest one
+async fn write_lines(recv: Receiver<String>, output: Option<PathBuf>) {
+    let mut file = None;
+    let mut last = None;
+
+    while let Ok(mut line) = recv.recv().await {
+        while let Ok(newer) = recv.try_recv() {
+            line = newer;
+        }
+        if last.as_ref() == Some(&line) {
+            continue;
+        }
+
+        if let Err(err) = write_line(&output, &mut file, &line).await {
+            // the reader of a FIFO may have gone away, so try again with a new reader once
+            debug!("could not write status line, reopening output: {err}");
+            file = None;
+            if let Err(err) = write_line(&output, &mut file, &line).await {
+                warn!("Could not write status: {err}");
+                file = None;
+                continue;
+            }
+        }
+        last = Some(line);
+    }
+}
+
+/// Writes a single line to output, opening it first if file is None
+async fn write_line(output: &Option<PathBuf>, file: &mut Option<File>, line: &str) -> io::Result<()> {
+    let line = format!("{line}\n");
+
+    let Some(path) = output else {
+        let mut stdout = io::stdout();
+        stdout.write_all(line.as_bytes()).await?;
+        return stdout.flush().await;
+    };
+
+    if file.is_none() {
+        // opening a FIFO blocks until there is a reader
+        *file = Some(OpenOptions::new().create(true).append(true).open(path).await?);
+    }
+    let file = file.as_mut().unwrap();
+
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await
+}
+
+/// Renders the line describing the status. The text is empty if there is no current song
+fn status_line(format: StatusFormat, template: &str, status: &Status) -> String {
+    let text = match status.current_song {
+        Some(_) => render_status_template(template, status).replace('\n', " "),
+        None => String::new(),
+    };
+
+    match format {
+        StatusFormat::Text => text,
+        StatusFormat::Json => serde_json::json!({
+            "text": text,
+            "tooltip": text,
+            "alt": status.state.as_str(),
+            "class": status.state.as_str(),
+        })
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{PlayState, Song};
+
+    fn status() -> Status {
+        let mut status = Status::new();
+        status.state = PlayState::Paused;
+        status.current_song = Some(Song::test_song());
+        status
+    }
+
+    #[test]
+    fn test_text_line() {
+        let line = status_line(StatusFormat::Text, "{artist} - {title}", &status());
+
+        assert_eq!(line, "Lena Raine, 2 Mello - Resurrections");
+    }
+
+    #[test]
+    fn test_json_line() {
+        let line = status_line(StatusFormat::Json, "{title}", &status());
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(json["text"], "Resurrections");
+        assert_eq!(json["class"], "paused");
+    }
+
+    #[test]
+    fn test_line_without_song() {
+        let line = status_line(StatusFormat::Json, "{title}", &Status::new());
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(json["text"], "");
+        assert_eq!(json["class"], "stopped");
+    }
+}
@@ -1,4 +1,4 @@
-use std::{env, io, path::PathBuf, process::exit};
+use std::{env, path::PathBuf, process::exit};
 
 use crate::HOME_DIR;
 
@@ -21,7 +21,22 @@ pub fn get_config_path() -> PathBuf {
     paths.into_iter().nth(idx).unwrap()
 }
 
-pub fn init_logger(level: log::LevelFilter) {
+/// Gets the directory mpdris stores cached data in.
+/// Defined as: $XDG_CACHE_HOME/mpdris or $HOME/.cache/mpdris
+pub fn get_cache_dir() -> PathBuf {
+    let base = env::var_os("XDG_CACHE_HOME").map_or_else(|| HOME_DIR.join(".cache"), PathBuf::from);
+    base.join("mpdris")
+}
+
+/// Gets the directory mpdris stores persistent data in.
+/// Defined as: $XDG_DATA_HOME/mpdris or $HOME/.local/share/mpdris
+pub fn get_data_dir() -> PathBuf {
+    let base = env::var_os("XDG_DATA_HOME").map_or_else(|| HOME_DIR.join(".local/share"), PathBuf::from);
+    base.join("mpdris")
+}
+
+/// Initializes the logger. If stderr_only is true, all messages are written to stderr, so that stdout can be used for other output
+pub fn init_logger(level: log::LevelFilter, stderr_only: bool) {
     use simplelog::format_description;
 
     let logconf = simplelog::ConfigBuilder::new()
@@ -31,27 +46,13 @@ pub fn init_logger(level: log::LevelFilter) {
         .expect("failed to get UTC offset")
         .build();
 
-    simplelog::TermLogger::init(level, logconf, simplelog::TerminalMode::Mixed, simplelog::ColorChoice::Auto)
-        .expect("failed to set logger");
-}
+    let mode = if stderr_only {
+        simplelog::TerminalMode::Stderr
+    } else {
+        simplelog::TerminalMode::Mixed
+    };
 
-/// Sends a signal to the specified PID, uses libc::kill as the underlying implementation
-///
-/// For more information see the libc documentation for kill
-///
-/// # Errors:
-/// [InvalidInput](io::ErrorKind::InvalidInput): An invalid signal was specified.<br />
-/// [PermissionDenied](io::ErrorKind::PermissionDenied): The calling process does not have
-/// permission to send the signal to any of the target processes.<br />
-/// [Uncategorized](io::ErrorKind::Uncategorized): The target process or process group does not exist.
-pub fn send_sig(pid: u32, signal: i32) -> io::Result<()> {
-    unsafe {
-        if libc::kill(pid as i32, signal) != 0 {
-            Err(io::Error::last_os_error())
-        } else {
-            Ok(())
-        }
-    }
+    simplelog::TermLogger::init(level, logconf, mode, simplelog::ColorChoice::Auto).expect("failed to set logger");
 }
 
 /// Forks the currently running process, kills the parent,
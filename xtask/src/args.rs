@@ -79,7 +79,7 @@ fn default_pkgname() -> Cow<'static, str> {
             env::VarError::NotPresent => Cow::Borrowed(NAME),
             env::VarError::NotUnicode(_) => panic!("$pkgname set, but contains non-unicode values"),
         },
-        |s| Cow::Owned(s),
+        Cow::Owned,
     )
 }
 